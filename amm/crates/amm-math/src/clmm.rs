@@ -0,0 +1,274 @@
+//! Concentrated-liquidity math. Prices are square roots of (N per M) as Q64.64 numbers,
+//! and tick i sits at price 1.0001^i, so sqrt_price(i) = 1.0001^(i / 2).
+
+use crate::curve::{fee_amount, FEE_DENOMINATOR};
+use crate::error::{ensure, MathError, Result};
+
+/// 1.0 as a Q64.64 fixed-point number.
+pub const Q64: u128 = 1 << 64;
+
+/// Tick bounds, chosen so every sqrt price stays within [2^-32, 2^32].
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+
+/// 2^64 / 1.0001^(2^i / 2) for each bit i of a tick index.
+const TICK_RATIOS: [u128; 19] = [
+    0xfffcb933bd6fad37,
+    0xfff97272373d4132,
+    0xfff2e50f5f656932,
+    0xffe5caca7e10e4e6,
+    0xffcb9843d60f6159,
+    0xff973b41fa98c081,
+    0xff2ea16466c96a38,
+    0xfe5dee046a99a2a8,
+    0xfcbe86c7900a88ae,
+    0xf987a7253ac41317,
+    0xf3392b0822b70005,
+    0xe7159475a2c29b74,
+    0xd097f3bdfd2022b8,
+    0xa9f746462d870fdf,
+    0x70d869a156d2a1b8,
+    0x31be135f97d08fd9,
+    0x9aa508b5b7a84e1,
+    0x5d6af8dedb8119,
+    0x2216e584f5fa,
+];
+
+/// Full 256-bit product of two u128s as (high, low) words.
+fn mul_wide(a: u128, b: u128) -> (u128, u128) {
+    let (a_hi, a_lo) = (a >> 64, a & (Q64 - 1));
+    let (b_hi, b_lo) = (b >> 64, b & (Q64 - 1));
+
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_hi = a_hi * b_hi;
+
+    // Middle partial products, carried into the high word
+    let cross = (lo_lo >> 64) + (hi_lo & (Q64 - 1)) + (lo_hi & (Q64 - 1));
+    let low = (cross << 64) | (lo_lo & (Q64 - 1));
+    let high = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (cross >> 64);
+
+    (high, low)
+}
+
+/// a · b / denominator with a 256-bit intermediate, for results that fit in a u128.
+pub fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> Result<u128> {
+    ensure!(denominator > 0, MathError::Overflow);
+
+    let (high, low) = mul_wide(a, b);
+    let (quotient, remainder) = match high {
+        0 => (low / denominator, low % denominator),
+        _ => {
+            ensure!(high < denominator, MathError::Overflow);
+
+            // Binary long division of the 256-bit product
+            let (mut quotient, mut remainder) = (0u128, high);
+            for bit in (0..128).rev() {
+                let carry = remainder >> 127;
+                remainder = (remainder << 1) | ((low >> bit) & 1);
+                quotient <<= 1;
+                if carry == 1 || remainder >= denominator {
+                    remainder = remainder.wrapping_sub(denominator);
+                    quotient |= 1;
+                }
+            }
+            (quotient, remainder)
+        }
+    };
+
+    match round_up && remainder > 0 {
+        true => quotient.checked_add(1).ok_or(MathError::Overflow),
+        false => Ok(quotient),
+    }
+}
+
+/// Sqrt price at a tick, as a Q64.64 number.
+pub fn sqrt_price_at_tick(tick: i32) -> Result<u128> {
+    ensure!((MIN_TICK..=MAX_TICK).contains(&tick), MathError::InvalidTick);
+
+    // Multiply together 1.0001^-(2^i / 2) for every set bit, then invert for positive ticks
+    let abs = tick.unsigned_abs();
+    let mut ratio = Q64;
+    for (bit, factor) in TICK_RATIOS.iter().enumerate() {
+        if abs & (1 << bit) != 0 {
+            ratio = (ratio * factor) >> 64;
+        }
+    }
+
+    Ok(match tick > 0 {
+        true => u128::MAX / ratio,
+        false => ratio,
+    })
+}
+
+/// Greatest tick whose sqrt price does not exceed `sqrt_price`.
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> Result<i32> {
+    ensure!(sqrt_price >= sqrt_price_at_tick(MIN_TICK)? && sqrt_price <= sqrt_price_at_tick(MAX_TICK)?, MathError::InvalidSqrtPrice);
+
+    let (mut lo, mut hi) = (MIN_TICK, MAX_TICK);
+    while lo < hi {
+        let mid = lo + (hi - lo + 1) / 2;
+        if sqrt_price_at_tick(mid)? <= sqrt_price {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+
+    Ok(lo)
+}
+
+/// Token M needed to move `liquidity` between two sqrt prices: L · (b − a) / (a · b).
+pub fn amount_m_delta(sqrt_price_a: u128, sqrt_price_b: u128, liquidity: u128, round_up: bool) -> Result<u128> {
+    let (lower, upper) = (sqrt_price_a.min(sqrt_price_b), sqrt_price_a.max(sqrt_price_b));
+    ensure!(lower > 0, MathError::InvalidSqrtPrice);
+
+    let scaled = mul_div(liquidity, upper - lower, upper, round_up)?;
+    mul_div(scaled, Q64, lower, round_up)
+}
+
+/// Token N needed to move `liquidity` between two sqrt prices: L · (b − a).
+pub fn amount_n_delta(sqrt_price_a: u128, sqrt_price_b: u128, liquidity: u128, round_up: bool) -> Result<u128> {
+    let (lower, upper) = (sqrt_price_a.min(sqrt_price_b), sqrt_price_a.max(sqrt_price_b));
+    mul_div(liquidity, upper - lower, Q64, round_up)
+}
+
+/// Tokens M and N backing `liquidity` over [tick_lower, tick_upper) at the current price.
+/// Below the range it is all M, above it all N.
+pub fn amounts_for_liquidity(
+    tick_current: i32,
+    sqrt_price: u128,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<(u64, u64)> {
+    let (sqrt_lower, sqrt_upper) = (sqrt_price_at_tick(tick_lower)?, sqrt_price_at_tick(tick_upper)?);
+    let (amount_m, amount_n) = if tick_current < tick_lower {
+        (amount_m_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?, 0)
+    } else if tick_current < tick_upper {
+        (
+            amount_m_delta(sqrt_price, sqrt_upper, liquidity, round_up)?,
+            amount_n_delta(sqrt_lower, sqrt_price, liquidity, round_up)?,
+        )
+    } else {
+        (0, amount_n_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?)
+    };
+
+    Ok((
+        u64::try_from(amount_m).map_err(|_| MathError::Overflow)?,
+        u64::try_from(amount_n).map_err(|_| MathError::Overflow)?,
+    ))
+}
+
+/// Sqrt price after adding `amount_in` of token M (`is_m`, price falls) or token N (price rises).
+/// Rounded so the pool never gives out more than the input pays for.
+fn next_sqrt_price_from_input(sqrt_price: u128, liquidity: u128, amount_in: u64, is_m: bool) -> Result<u128> {
+    match is_m {
+        // L / (L / P + in)
+        true => {
+            let virtual_m = mul_div(liquidity, Q64, sqrt_price, false)?;
+            let denominator = virtual_m
+                .checked_add(amount_in as u128)
+                .ok_or(MathError::Overflow)?;
+            mul_div(liquidity, Q64, denominator, true)
+        }
+        // P + in / L
+        false => sqrt_price
+            .checked_add(mul_div(amount_in as u128, Q64, liquidity, false)?)
+            .ok_or(MathError::Overflow),
+    }
+}
+
+/// One leg of a concentrated-liquidity swap, from the current price towards a target tick.
+pub struct SwapStep {
+    pub sqrt_price_next: u128,
+    pub amount_in: u64, // Input priced by the curve, excluding the fee
+    pub amount_out: u64,
+    pub fee_amount: u64,
+}
+
+/// Prices an exact-input step paying in token M (`is_m`) or token N that either reaches
+/// `sqrt_price_target` or exhausts `amount_remaining`. The fee is taken from the input,
+/// as on the other curves.
+pub fn compute_swap_step(
+    is_m: bool,
+    sqrt_price: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u64,
+    fee: u16,
+) -> Result<SwapStep> {
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+    let amount_remaining_less_fee = amount_remaining - fee_amount(amount_remaining, fee)?;
+
+    let amount_in_to_target = match is_m {
+        true => amount_m_delta(sqrt_price_target, sqrt_price, liquidity, true)?,
+        false => amount_n_delta(sqrt_price, sqrt_price_target, liquidity, true)?,
+    };
+
+    let reaches_target = amount_remaining_less_fee as u128 >= amount_in_to_target;
+    let (sqrt_price_next, amount_in) = match reaches_target {
+        true => (sqrt_price_target, amount_in_to_target),
+        false => {
+            let next = next_sqrt_price_from_input(sqrt_price, liquidity, amount_remaining_less_fee, is_m)?;
+            let amount_in = match is_m {
+                true => amount_m_delta(next, sqrt_price, liquidity, true)?,
+                false => amount_n_delta(sqrt_price, next, liquidity, true)?,
+            };
+            (next, amount_in)
+        }
+    };
+
+    let amount_out = match is_m {
+        true => amount_n_delta(sqrt_price_next, sqrt_price, liquidity, false)?,
+        false => amount_m_delta(sqrt_price, sqrt_price_next, liquidity, false)?,
+    };
+
+    let amount_in = u64::try_from(amount_in).map_err(|_| MathError::Overflow)?;
+    let amount_out = u64::try_from(amount_out).map_err(|_| MathError::Overflow)?;
+
+    // A step that stops short keeps the whole remainder, so rounding dust becomes fee
+    let fee_amount = match reaches_target {
+        true => u64::try_from(
+            (amount_in as u128 * fee as u128).div_ceil(FEE_DENOMINATOR - fee as u128),
+        )
+        .map_err(|_| MathError::Overflow)?,
+        false => amount_remaining
+            .checked_sub(amount_in)
+            .ok_or(MathError::Underflow)?,
+    };
+
+    Ok(SwapStep { sqrt_price_next, amount_in, amount_out, fee_amount })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_rounds_a_128_bit_product() {
+        assert_eq!(mul_div(6, 7, 4, false), Ok(10));
+        assert_eq!(mul_div(6, 7, 4, true), Ok(11));
+        assert_eq!(mul_div(6, 8, 4, true), Ok(12));
+        assert_eq!(mul_div(1, 1, 0, false), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn mul_div_divides_a_256_bit_product() {
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, false), Ok(u128::MAX));
+        assert_eq!(mul_div(1 << 100, 1 << 100, 1 << 90, false), Ok(1 << 110));
+        assert_eq!(mul_div(u128::MAX, Q64, Q64, true), Ok(u128::MAX));
+
+        // A denominator above 2^127 exercises the carry out of the shifted remainder
+        assert_eq!(mul_div(u128::MAX, 5, u128::MAX - 1, false), Ok(5));
+        assert_eq!(mul_div(u128::MAX, 5, u128::MAX - 1, true), Ok(6));
+    }
+
+    #[test]
+    fn mul_div_rejects_quotients_beyond_128_bits() {
+        assert_eq!(mul_div(u128::MAX, 3, 2, false), Err(MathError::Overflow));
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX - 1, true), Err(MathError::Overflow));
+    }
+}
@@ -0,0 +1,465 @@
+//! Pricing curves for two-token pools, and conversions between LP and reserves.
+
+use crate::error::{ensure, MathError, Result};
+
+/// To protect the pool from unfair advantages, a small amount of liquidity is always
+/// minted and sent to a "dead" address when the pool is first created.
+/// This ensures the first person to deposit doesn't receive excessive LP tokens.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Fees are expressed in basis points, so 10_000 represents 100%.
+pub const FEE_DENOMINATOR: u128 = 10_000;
+
+/// Quotes a constant-product (x * y = k) swap for an exact input amount.
+/// The fee is taken from the input before pricing, so it stays in the pool for LPs.
+/// The result is rounded down, in favour of the pool.
+pub fn swap_exact_in(reserve_in: u64, reserve_out: u64, amount_in: u64, fee: u16) -> Result<u64> {
+    ensure!(reserve_in > 0 && reserve_out > 0, MathError::NoLiquidityInPool);
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+
+    let amount_in_after_fee = (amount_in as u128)
+        .checked_mul(FEE_DENOMINATOR - fee as u128)
+        .ok_or(MathError::Overflow)?
+        / FEE_DENOMINATOR;
+
+    // out = reserve_out * in / (reserve_in + in)
+    let numerator = (reserve_out as u128)
+        .checked_mul(amount_in_after_fee)
+        .ok_or(MathError::Overflow)?;
+    let denominator = (reserve_in as u128)
+        .checked_add(amount_in_after_fee)
+        .ok_or(MathError::Overflow)?;
+
+    u64::try_from(numerator / denominator).map_err(|_| MathError::Overflow)
+}
+
+/// Portion of `amount_in` kept as the swap fee, i.e. what is not priced by the curve.
+pub fn fee_amount(amount_in: u64, fee: u16) -> Result<u64> {
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+
+    let amount_in_after_fee = (amount_in as u128)
+        .checked_mul(FEE_DENOMINATOR - fee as u128)
+        .ok_or(MathError::Overflow)?
+        / FEE_DENOMINATOR;
+
+    Ok(amount_in - amount_in_after_fee as u64)
+}
+
+/// Treasury's cut of a swap fee, with `protocol_fee` expressed in basis points of the fee.
+pub fn protocol_fee_amount(fee_amount: u64, protocol_fee: u16) -> u64 {
+    ((fee_amount as u128) * protocol_fee as u128 / FEE_DENOMINATOR) as u64
+}
+
+/// Quotes the input required for a constant-product swap to pay out exactly `amount_out`.
+/// Both the pre-fee input and the fee gross-up are rounded up, in favour of the pool.
+pub fn swap_exact_out(reserve_in: u64, reserve_out: u64, amount_out: u64, fee: u16) -> Result<u64> {
+    ensure!(reserve_in > 0 && reserve_out > 0, MathError::NoLiquidityInPool);
+    ensure!(amount_out < reserve_out, MathError::NoLiquidityInPool);
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+
+    // in = reserve_in * out / (reserve_out - out)
+    let numerator = (reserve_in as u128)
+        .checked_mul(amount_out as u128)
+        .ok_or(MathError::Overflow)?;
+    let amount_in_after_fee = numerator.div_ceil((reserve_out - amount_out) as u128);
+
+    gross_up_fee(amount_in_after_fee, fee)
+}
+
+/// Grosses an input back up so that, after the fee is deducted, `amount_in_after_fee` remains.
+fn gross_up_fee(amount_in_after_fee: u128, fee: u16) -> Result<u64> {
+    let amount_in = amount_in_after_fee
+        .checked_mul(FEE_DENOMINATOR)
+        .ok_or(MathError::Overflow)?
+        .div_ceil(FEE_DENOMINATOR - fee as u128);
+
+    u64::try_from(amount_in).map_err(|_| MathError::Overflow)
+}
+
+/// Sale that leaves the rest of `amount_in` in proportion to a constant-product pool after
+/// the swap. With the fee taken from the sale s, R = reserve_in, D = FEE_DENOMINATOR and
+/// g = D − fee, it solves g·s² + R·(D + g)·s − D·R·amount_in = 0:
+/// s = (sqrt((R·(D + g))² + 4·g·D·amount_in·R) − R·(D + g)) / 2g.
+/// The protocol fee's share is ignored and the square root is taken on operands scaled to
+/// fit 128 bits, so this is an estimate a few units from the exact crossing.
+pub(crate) fn constant_product_zap_swap(reserve_in: u64, amount_in: u64, fee: u16) -> Result<u64> {
+    let g = FEE_DENOMINATOR - fee as u128;
+    let b = reserve_in as u128 * (FEE_DENOMINATOR + g);
+    let c = 4 * g * FEE_DENOMINATOR * amount_in as u128;
+
+    // Scale b² and c·R down by 4^k so that their sum fits a u128
+    let bits = |x: u128| 128 - x.leading_zeros();
+    let k = bits(b)
+        .saturating_sub(63)
+        .max((bits(c) + bits(reserve_in as u128)).saturating_sub(126).div_ceil(2));
+    let b_scaled = b >> k;
+    let cr_scaled = crate::clmm::mul_div(c, reserve_in as u128, 1 << (2 * k), false)?;
+    let root = integer_sqrt(b_scaled * b_scaled + cr_scaled) << k;
+
+    Ok((root.saturating_sub(b) / (2 * g)).min(amount_in as u128) as u64)
+}
+
+/// Newton iterations allowed before a StableSwap computation is treated as divergent.
+const STABLE_MAX_ITERATIONS: usize = 255;
+
+fn mul_div(a: u128, b: u128, denominator: u128) -> Result<u128> {
+    Ok(a.checked_mul(b).ok_or(MathError::Overflow)? / denominator)
+}
+
+/// Ann = amp · n^n for a two-token pool. A zero amplification has no invariant.
+fn stable_ann(amp: u64) -> Result<u128> {
+    ensure!(amp > 0, MathError::CurveError);
+    Ok(amp as u128 * 4)
+}
+
+/// StableSwap invariant D of a two-token pool, found with Newton's method from
+/// Ann·(x + y) + D = Ann·D + D³ / (4·x·y), where Ann = amp · n^n and n = 2.
+/// Pools too deep for the u128 intermediates fail with Overflow rather than misprice.
+pub fn stable_invariant(reserve_x: u64, reserve_y: u64, amp: u64) -> Result<u128> {
+    ensure!(reserve_x > 0 && reserve_y > 0, MathError::NoLiquidityInPool);
+
+    let (x, y) = (reserve_x as u128, reserve_y as u128);
+    let sum = x.checked_add(y).ok_or(MathError::Overflow)?;
+    let ann = stable_ann(amp)?;
+    let (x2, y2) = (
+        x.checked_mul(2).ok_or(MathError::Overflow)?,
+        y.checked_mul(2).ok_or(MathError::Overflow)?,
+    );
+
+    let mut d = sum;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        // d_p = D³ / (4·x·y)
+        let d_p = mul_div(mul_div(d, d, x2)?, d, y2)?;
+
+        // D = (Ann·S + 2·d_p)·D / ((Ann − 1)·D + 3·d_p)
+        let numerator = ann
+            .checked_mul(sum)
+            .and_then(|n| n.checked_add(d_p.checked_mul(2)?))
+            .and_then(|n| n.checked_mul(d))
+            .ok_or(MathError::Overflow)?;
+        let denominator = (ann - 1)
+            .checked_mul(d)
+            .and_then(|n| n.checked_add(d_p.checked_mul(3)?))
+            .ok_or(MathError::Overflow)?;
+
+        let previous = d;
+        d = numerator / denominator;
+        if d.abs_diff(previous) <= 1 {
+            return Ok(d);
+        }
+    }
+
+    Err(MathError::CurveError)
+}
+
+/// Balance of one token that keeps the invariant at `d` when the other side holds `x`,
+/// solving y² + (b − D)·y = c with c = D³ / (4·x·Ann) and b = x + D / Ann.
+fn stable_y(x: u128, d: u128, amp: u64) -> Result<u128> {
+    ensure!(x > 0, MathError::NoLiquidityInPool);
+
+    let ann = stable_ann(amp)?;
+    let c = mul_div(
+        mul_div(d, d, x.checked_mul(2).ok_or(MathError::Overflow)?)?,
+        d,
+        ann.checked_mul(2).ok_or(MathError::Overflow)?,
+    )?;
+    let b = x.checked_add(d / ann).ok_or(MathError::Overflow)?;
+
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        // y = (y² + c) / (2·y + b − D)
+        let numerator = y
+            .checked_mul(y)
+            .and_then(|n| n.checked_add(c))
+            .ok_or(MathError::Overflow)?;
+        let denominator = y
+            .checked_mul(2)
+            .and_then(|n| n.checked_add(b))
+            .ok_or(MathError::Overflow)?
+            .checked_sub(d)
+            .ok_or(MathError::CurveError)?;
+
+        let previous = y;
+        y = numerator / denominator;
+        if y.abs_diff(previous) <= 1 {
+            return Ok(y);
+        }
+    }
+
+    Err(MathError::CurveError)
+}
+
+/// Quotes a StableSwap swap for an exact input amount. As with the constant-product curve,
+/// the fee is taken from the input before pricing. One unit is held back from the output
+/// to absorb Newton rounding, in favour of the pool.
+pub fn stable_swap_exact_in(reserve_in: u64, reserve_out: u64, amount_in: u64, fee: u16, amp: u64) -> Result<u64> {
+    let amount_in_after_fee = amount_in - fee_amount(amount_in, fee)?;
+    let d = stable_invariant(reserve_in, reserve_out, amp)?;
+
+    let new_reserve_in = reserve_in as u128 + amount_in_after_fee as u128;
+    let new_reserve_out = stable_y(new_reserve_in, d, amp)?;
+    let amount_out = (reserve_out as u128)
+        .saturating_sub(new_reserve_out)
+        .saturating_sub(1);
+
+    u64::try_from(amount_out).map_err(|_| MathError::Overflow)
+}
+
+/// Quotes the input required for a StableSwap swap to pay out exactly `amount_out`,
+/// rounded up in favour of the pool.
+pub fn stable_swap_exact_out(reserve_in: u64, reserve_out: u64, amount_out: u64, fee: u16, amp: u64) -> Result<u64> {
+    ensure!(amount_out < reserve_out, MathError::NoLiquidityInPool);
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+
+    let d = stable_invariant(reserve_in, reserve_out, amp)?;
+    let new_reserve_in = stable_y((reserve_out - amount_out) as u128, d, amp)? + 1;
+    let amount_in_after_fee = new_reserve_in
+        .checked_sub(reserve_in as u128)
+        .ok_or(MathError::CurveError)?;
+
+    gross_up_fee(amount_in_after_fee, fee)
+}
+
+/// Marginal StableSwap price of the base token in the quote token as a Q64.64 number.
+/// Differentiating the invariant gives (K + y) / (K + x) with K = 4·Ann·x²·y² / D³,
+/// evaluated here on reserves normalised by D.
+pub fn stable_spot_price(reserve_quote: u64, reserve_base: u64, amp: u64) -> Result<u128> {
+    let d = stable_invariant(reserve_base, reserve_quote, amp)?;
+    let ann = stable_ann(amp)?;
+
+    // x / D and y / D as Q32.32
+    let x = ((reserve_base as u128) << 32) / d;
+    let y = ((reserve_quote as u128) << 32) / d;
+    let xy = x.checked_mul(y).ok_or(MathError::Overflow)? >> 32;
+    let k = xy
+        .checked_mul(xy)
+        .and_then(|n| (n >> 32).checked_mul(ann.checked_mul(4)?))
+        .ok_or(MathError::Overflow)?;
+
+    let numerator = k
+        .checked_add(y)
+        .and_then(|n| n.checked_mul(1 << 64))
+        .ok_or(MathError::Overflow)?;
+    let denominator = k.checked_add(x).ok_or(MathError::Overflow)?;
+    numerator.checked_div(denominator).ok_or(MathError::CurveError)
+}
+
+/// Computes the integer square root of a number using Newton's approximation method.
+pub fn integer_sqrt(n: u128) -> u128 {
+    if n == 0 {
+        return 0;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/// Converts an amount of LP into its share of a single reserve: reserve * lp / total_lp.
+/// Deposits round up and withdrawals round down, so the pool never loses on rounding.
+pub fn lp_to_token(lp_amount: u64, reserve: u64, total_lp: u64, round_up: bool) -> Result<u64> {
+    ensure!(total_lp > 0, MathError::PoolNotInitialized);
+
+    let numerator = (reserve as u128)
+        .checked_mul(lp_amount as u128)
+        .ok_or(MathError::Overflow)?;
+    let amount = match round_up {
+        true => numerator.div_ceil(total_lp as u128),
+        false => numerator / total_lp as u128,
+    };
+
+    u64::try_from(amount).map_err(|_| MathError::Overflow)
+}
+
+/// Converts a token amount into the LP it is worth against one reserve, rounded down.
+pub fn token_to_lp(amount: u64, reserve: u64, total_lp: u64) -> Result<u64> {
+    ensure!(reserve > 0, MathError::NoLiquidityInPool);
+
+    let lp = (amount as u128)
+        .checked_mul(total_lp as u128)
+        .ok_or(MathError::Overflow)?
+        / reserve as u128;
+
+    u64::try_from(lp).map_err(|_| MathError::Overflow)
+}
+
+/// Total LP backing the reserves. `initialize` never mints the MINIMUM_LIQUIDITY share,
+/// so it is added back here to keep it permanently locked in the pool.
+pub fn total_lp_supply(minted_supply: u64) -> Result<u64> {
+    minted_supply
+        .checked_add(MINIMUM_LIQUIDITY)
+        .ok_or(MathError::Overflow)
+}
+
+/// Spot price of one reserve in terms of the other as a Q64.64 fixed-point number.
+pub fn q64_price(reserve_quote: u64, reserve_base: u64) -> Result<u128> {
+    ensure!(reserve_base > 0, MathError::NoLiquidityInPool);
+    Ok(((reserve_quote as u128) << 64) / reserve_base as u128)
+}
+
+/// 1.0 as a Q64.64 fixed-point number.
+const Q64_ONE: u128 = 1 << 64;
+
+/// ln(2) as a Q64.64 fixed-point number.
+const Q64_LN_2: u128 = 0xB172_17F7_D1CF_79AB;
+
+/// Relative slack, 2^-40, applied to fixed-point powers so their truncation error
+/// always rounds in favour of the pool.
+const POW_ERROR_SHIFT: u32 = 40;
+
+/// Base-2 logarithm of a Q64.64 number no smaller than one, rounded down.
+fn log2_q64(x: u128) -> u128 {
+    let integer = 63 - x.leading_zeros() as u128;
+    let mut result = integer << 64;
+
+    // Normalise into [1, 2), then square repeatedly: each overflow past 2 yields the next bit
+    let mut y = x >> integer;
+    for bit in (0..64).rev() {
+        let fraction = y - Q64_ONE;
+        y = Q64_ONE + 2 * fraction + ((fraction * fraction) >> 64);
+        if y >= 2 * Q64_ONE {
+            y >>= 1;
+            result |= 1 << bit;
+        }
+    }
+
+    result
+}
+
+/// 2^t for a non-negative Q64.64 exponent below 64, as a Q64.64 number rounded down.
+fn exp2_q64(t: u128) -> Result<u128> {
+    let integer = t >> 64;
+    ensure!(integer < 64, MathError::Overflow);
+
+    // 2^f = e^(f·ln 2), summed as a Taylor series; every term is positive and below one
+    let z = ((t & (Q64_ONE - 1)) * Q64_LN_2) >> 64;
+    let (mut sum, mut term) = (Q64_ONE, Q64_ONE);
+    for k in 1..40 {
+        term = ((term * z) >> 64) / k;
+        if term == 0 {
+            break;
+        }
+        sum += term;
+    }
+
+    sum.checked_shl(integer as u32)
+        .filter(|r| r >> integer == sum)
+        .ok_or(MathError::Overflow)
+}
+
+/// base^(numerator / denominator) for a Q64.64 base of at least one, rounded down
+/// when `round_up` is unset and up otherwise.
+fn pow_q64(base: u128, numerator: u16, denominator: u16, round_up: bool) -> Result<u128> {
+    let exponent = log2_q64(base) * numerator as u128 / denominator as u128;
+    let power = exp2_q64(exponent)?;
+    let slack = (power >> POW_ERROR_SHIFT) + 1;
+
+    Ok(match round_up {
+        true => power.checked_add(slack).ok_or(MathError::Overflow)?,
+        false => power.saturating_sub(slack).max(Q64_ONE),
+    })
+}
+
+/// Quotes a weighted-pool swap for an exact input amount, with weights in basis points:
+/// out = reserve_out · (1 − (reserve_in / (reserve_in + in))^(weight_in / weight_out)).
+/// The fee is taken from the input before pricing and the result is rounded down.
+pub fn weighted_swap_exact_in(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_in: u64,
+    fee: u16,
+    weight_in: u16,
+    weight_out: u16,
+) -> Result<u64> {
+    ensure!(reserve_in > 0 && reserve_out > 0, MathError::NoLiquidityInPool);
+
+    let amount_in_after_fee = amount_in - fee_amount(amount_in, fee)?;
+
+    // growth = ((reserve_in + in) / reserve_in)^(weight_in / weight_out), so out = reserve_out · (1 − 1 / growth)
+    let base = (reserve_in as u128 + amount_in_after_fee as u128)
+        .checked_mul(Q64_ONE)
+        .ok_or(MathError::Overflow)?
+        / reserve_in as u128;
+    let growth = pow_q64(base, weight_in, weight_out, false)?;
+    let amount_out = mul_div(reserve_out as u128, growth - Q64_ONE, growth)?;
+
+    u64::try_from(amount_out).map_err(|_| MathError::Overflow)
+}
+
+/// Quotes the input required for a weighted-pool swap to pay out exactly `amount_out`:
+/// in = reserve_in · ((reserve_out / (reserve_out − out))^(weight_out / weight_in) − 1),
+/// rounded up in favour of the pool.
+pub fn weighted_swap_exact_out(
+    reserve_in: u64,
+    reserve_out: u64,
+    amount_out: u64,
+    fee: u16,
+    weight_in: u16,
+    weight_out: u16,
+) -> Result<u64> {
+    ensure!(reserve_in > 0 && reserve_out > 0, MathError::NoLiquidityInPool);
+    ensure!(amount_out < reserve_out, MathError::NoLiquidityInPool);
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+
+    let base = (reserve_out as u128 * Q64_ONE).div_ceil((reserve_out - amount_out) as u128);
+    let growth = pow_q64(base, weight_out, weight_in, true)?;
+    let amount_in_after_fee = (reserve_in as u128)
+        .checked_mul(growth - Q64_ONE)
+        .ok_or(MathError::Overflow)?
+        .div_ceil(Q64_ONE);
+
+    gross_up_fee(amount_in_after_fee, fee)
+}
+
+/// Weighted invariant reserve_x^weight_x · reserve_y^weight_y with weights normalised
+/// to one, rounded down. With equal weights this is sqrt(x · y).
+pub fn weighted_invariant(reserve_x: u64, reserve_y: u64, weight_x: u16, weight_y: u16) -> Result<u64> {
+    ensure!(reserve_x > 0 && reserve_y > 0, MathError::NoLiquidityInPool);
+
+    let total = weight_x as u128 + weight_y as u128;
+    let exponent = (log2_q64((reserve_x as u128) << 64) * weight_x as u128
+        + log2_q64((reserve_y as u128) << 64) * weight_y as u128)
+        / total;
+    let invariant = exp2_q64(exponent)?;
+    let invariant = invariant.saturating_sub((invariant >> POW_ERROR_SHIFT) + 1) >> 64;
+
+    u64::try_from(invariant).map_err(|_| MathError::Overflow)
+}
+
+/// Marginal weighted-pool price of the base token in the quote token as a Q64.64 number:
+/// (reserve_quote / weight_quote) / (reserve_base / weight_base).
+pub fn weighted_spot_price(reserve_quote: u64, reserve_base: u64, weight_quote: u16, weight_base: u16) -> Result<u128> {
+    mul_div(q64_price(reserve_quote, reserve_base)?, weight_base as u128, weight_quote as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::{quote_zap_in, Curve};
+
+    #[test]
+    fn swap_exact_in_and_fee_amount_reject_fees_of_100_percent_or_more() {
+        for fee in [10_000, 10_001, u16::MAX] {
+            assert_eq!(swap_exact_in(1_000_000, 1_000_000, 1_000, fee), Err(MathError::InvalidFee));
+            assert_eq!(fee_amount(1_000, fee), Err(MathError::InvalidFee));
+        }
+        assert_eq!(fee_amount(1_000, 9_999).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn constant_product_zap_estimate_lands_next_to_the_best_split() {
+        for amount_in in [1_000, 1_000_000, 100_000_000, 5_000_000_000] {
+            let estimate = constant_product_zap_swap(1_000_000_000, amount_in, 30).unwrap();
+            let quote =
+                quote_zap_in(Curve::ConstantProduct, 1_000_000_000, 2_000_000_000, 1_000_000_000, amount_in, 30, 0).unwrap();
+            assert!(estimate.abs_diff(quote.swap.amount_in) <= 2, "{amount_in}: {estimate} vs {}", quote.swap.amount_in);
+
+            // The deposit never takes more than the input left over or the swap paid out
+            assert!(quote.deposit_in + quote.swap.amount_in <= amount_in);
+            assert!(quote.deposit_out <= quote.swap.amount_out);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use core::fmt;
+
+/// Failures of the pool math. The program maps each onto its own error code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    Overflow,
+    Underflow,
+    NoLiquidityInPool,
+    PoolNotInitialized,
+    InvalidFee,
+    CurveError,
+    InvalidTick,
+    InvalidSqrtPrice,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            MathError::Overflow => "Overflow detected.",
+            MathError::Underflow => "Underflow detected.",
+            MathError::NoLiquidityInPool => "No liquidity in pool.",
+            MathError::PoolNotInitialized => "Pool not initialized.",
+            MathError::InvalidFee => "Fee is greater than 100%.",
+            MathError::CurveError => "Curve error.",
+            MathError::InvalidTick => "Invalid tick or tick range.",
+            MathError::InvalidSqrtPrice => "Invalid sqrt price.",
+        };
+        f.write_str(message)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, MathError>;
+
+/// Returns `$error` from the enclosing function unless `$condition` holds.
+macro_rules! ensure {
+    ($condition:expr, $error:expr) => {
+        if !$condition {
+            return Err($error);
+        }
+    };
+}
+pub(crate) use ensure;
@@ -0,0 +1,13 @@
+//! Pricing and quoting math for the amm program. The program executes swaps, deposits and
+//! withdrawals with these same functions, so quotes computed off-chain match on-chain
+//! execution to the unit.
+
+#![no_std]
+
+pub mod clmm;
+pub mod curve;
+pub mod error;
+pub mod quote;
+
+pub use error::{MathError, Result};
+pub use quote::*;
@@ -0,0 +1,427 @@
+//! Quotes for swaps, deposits and withdrawals, with the rounding the program applies.
+
+use crate::clmm::{mul_div, Q64};
+use crate::curve::{
+    constant_product_zap_swap, fee_amount, integer_sqrt, lp_to_token, protocol_fee_amount, q64_price, stable_spot_price,
+    stable_swap_exact_in, stable_swap_exact_out, swap_exact_in, swap_exact_out, token_to_lp, total_lp_supply,
+    weighted_invariant, weighted_spot_price, weighted_swap_exact_in, weighted_swap_exact_out, FEE_DENOMINATOR,
+};
+use crate::error::{ensure, MathError, Result};
+
+/// A pool's pricing curve, seen from the input side of a swap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve {
+    ConstantProduct,
+    StableSwap { amp: u64 },
+    Weighted { weight_in: u16, weight_out: u16 }, // Basis points, summing to 10_000
+}
+
+impl Curve {
+    /// The same curve seen from the other side of the pool.
+    pub fn reversed(self) -> Self {
+        match self {
+            Curve::Weighted { weight_in, weight_out } => Curve::Weighted { weight_in: weight_out, weight_out: weight_in },
+            curve => curve,
+        }
+    }
+}
+
+/// Amounts of a swap: `amount_in` is what reaches the pool and `fee` is the part of it
+/// not priced by the curve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+}
+
+/// Quotes a swap of exactly `amount_in`, with `fee` in basis points.
+pub fn quote_swap_exact_in(curve: Curve, reserve_in: u64, reserve_out: u64, amount_in: u64, fee: u16) -> Result<SwapQuote> {
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+
+    // Curves that round their invariant can otherwise quote dust for nothing
+    if amount_in == 0 {
+        return Ok(SwapQuote { amount_in: 0, amount_out: 0, fee: 0 });
+    }
+
+    let amount_out = match curve {
+        Curve::ConstantProduct => swap_exact_in(reserve_in, reserve_out, amount_in, fee)?,
+        Curve::StableSwap { amp } => stable_swap_exact_in(reserve_in, reserve_out, amount_in, fee, amp)?,
+        Curve::Weighted { weight_in, weight_out } => {
+            weighted_swap_exact_in(reserve_in, reserve_out, amount_in, fee, weight_in, weight_out)?
+        }
+    };
+
+    Ok(SwapQuote { amount_in, amount_out, fee: fee_amount(amount_in, fee)? })
+}
+
+/// Quotes the input, fee included, needed for a swap to pay out exactly `amount_out`.
+pub fn quote_swap_exact_out(curve: Curve, reserve_in: u64, reserve_out: u64, amount_out: u64, fee: u16) -> Result<SwapQuote> {
+    let amount_in = match curve {
+        Curve::ConstantProduct => swap_exact_out(reserve_in, reserve_out, amount_out, fee)?,
+        Curve::StableSwap { amp } => stable_swap_exact_out(reserve_in, reserve_out, amount_out, fee, amp)?,
+        Curve::Weighted { weight_in, weight_out } => {
+            weighted_swap_exact_out(reserve_in, reserve_out, amount_out, fee, weight_in, weight_out)?
+        }
+    };
+
+    Ok(SwapQuote { amount_in, amount_out, fee: fee_amount(amount_in, fee)? })
+}
+
+/// Tokens charged, rounded up, to mint `lp_amount` against a pool whose LP mint has
+/// `lp_supply` outstanding.
+pub fn quote_deposit(reserve_m: u64, reserve_n: u64, lp_supply: u64, lp_amount: u64) -> Result<(u64, u64)> {
+    let total_lp = total_lp_supply(lp_supply)?;
+    Ok((
+        lp_to_token(lp_amount, reserve_m, total_lp, true)?,
+        lp_to_token(lp_amount, reserve_n, total_lp, true)?,
+    ))
+}
+
+/// Tokens paid out, rounded down, for burning `lp_amount` from a pool whose LP mint has
+/// `lp_supply` outstanding.
+pub fn quote_withdraw(reserve_m: u64, reserve_n: u64, lp_supply: u64, lp_amount: u64) -> Result<(u64, u64)> {
+    let total_lp = total_lp_supply(lp_supply)?;
+    Ok((
+        lp_to_token(lp_amount, reserve_m, total_lp, false)?,
+        lp_to_token(lp_amount, reserve_n, total_lp, false)?,
+    ))
+}
+
+/// A single-sided deposit: `swap` sells part of the input for the other token, then
+/// `deposit_in` of the input and `deposit_out` of the proceeds are deposited for `lp_amount`.
+/// Proceeds left over from rounding, `swap.amount_out - deposit_out`, go back to the depositor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZapInQuote {
+    pub swap: SwapQuote,
+    pub deposit_in: u64,
+    pub deposit_out: u64,
+    pub lp_amount: u64,
+}
+
+/// Quotes depositing `amount_in` of one token only, against a pool whose LP mint has
+/// `lp_supply` outstanding. `protocol_fee` is the treasury's share of the swap fee in basis
+/// points, which leaves the reserves.
+///
+/// Selling more lowers the LP the remaining input is worth and raises the LP the proceeds
+/// are worth, so the most LP is minted where the two cross, i.e. where the remainder matches
+/// the pool ratio after the swap. The crossing is searched from an estimate: the closed-form
+/// optimum for constant-product pools, which lands within a few units of it, and the pool's
+/// value split at the spot price for the other curves.
+pub fn quote_zap_in(
+    curve: Curve,
+    reserve_in: u64,
+    reserve_out: u64,
+    lp_supply: u64,
+    amount_in: u64,
+    fee: u16,
+    protocol_fee: u16,
+) -> Result<ZapInQuote> {
+    ensure!((fee as u128) < FEE_DENOMINATOR, MathError::InvalidFee);
+    let total_lp = total_lp_supply(lp_supply)?;
+
+    // LP the remaining input and the proceeds are each worth after selling `swap_in`
+    let split = |swap_in: u64| -> Result<ZapSplit> {
+        let swap = quote_swap_exact_in(curve, reserve_in, reserve_out, swap_in, fee)?;
+        let reserve_in = reserve_in
+            .checked_add(swap_in - protocol_fee_amount(swap.fee, protocol_fee))
+            .ok_or(MathError::Overflow)?;
+        let reserve_out = reserve_out.checked_sub(swap.amount_out).ok_or(MathError::Underflow)?;
+        Ok(ZapSplit {
+            swap,
+            reserve_in,
+            reserve_out,
+            lp_in: token_to_lp(amount_in - swap_in, reserve_in, total_lp)?,
+            lp_out: token_to_lp(swap.amount_out, reserve_out, total_lp)?,
+        })
+    };
+
+    // Smallest sale whose proceeds are worth at least the remaining input
+    let estimate = match curve {
+        Curve::ConstantProduct => constant_product_zap_swap(reserve_in, amount_in, fee)?,
+        _ => spot_zap_swap(curve, reserve_in, reserve_out, amount_in),
+    };
+    let low = search_from(estimate, amount_in, |swap_in| {
+        let at = split(swap_in)?;
+        Ok(at.lp_out >= at.lp_in)
+    })?;
+
+    // The best split is on one side of the crossing or the other
+    let mut best = split(low)?;
+    if low > 0 {
+        let below = split(low - 1)?;
+        if below.lp_amount() > best.lp_amount() {
+            best = below;
+        }
+    }
+
+    let lp_amount = best.lp_amount();
+    Ok(ZapInQuote {
+        swap: best.swap,
+        deposit_in: lp_to_token(lp_amount, best.reserve_in, total_lp, true)?,
+        deposit_out: lp_to_token(lp_amount, best.reserve_out, total_lp, true)?,
+        lp_amount,
+    })
+}
+
+/// Sale that splits `amount_in` in the proportion the pool holds its value at the spot
+/// price. Exact for the smallest zaps; larger ones sell somewhat more to cover the price
+/// impact of their own swap.
+fn spot_zap_swap(curve: Curve, reserve_in: u64, reserve_out: u64, amount_in: u64) -> u64 {
+    // Value of the output reserve in units of the input token
+    let share = spot_price(curve, reserve_in, reserve_out)
+        .and_then(|price| mul_div(reserve_out as u128, Q64, price, false))
+        .and_then(|value_out| mul_div(amount_in as u128, value_out, reserve_in as u128 + value_out, false));
+
+    // Only a starting point for the search, so an unpriceable pool starts from an even split
+    match share {
+        Ok(share) => share.min(amount_in as u128) as u64,
+        Err(_) => amount_in / 2,
+    }
+}
+
+/// Smallest `x` in [0, max] at which the monotone `predicate` holds, given that it holds at
+/// `max`. Strides outwards from `guess` in doubling steps until the crossing is bracketed,
+/// then bisects the bracket, so it evaluates `predicate` at most about 2·log2(d) + 3 times
+/// for a guess `d` away: a handful from a close guess, and no more than twice a bisection
+/// of [0, max] from a poor one.
+fn search_from(guess: u64, max: u64, mut predicate: impl FnMut(u64) -> Result<bool>) -> Result<u64> {
+    let guess = guess.min(max);
+    let (mut low, mut high) = (0, guess);
+    let mut stride: u64 = 1;
+
+    if guess == max || predicate(guess)? {
+        while high > 0 {
+            let probe = high.saturating_sub(stride);
+            if !predicate(probe)? {
+                low = probe + 1;
+                break;
+            }
+            high = probe;
+            stride = stride.saturating_mul(2);
+        }
+    } else {
+        low = guess + 1;
+        loop {
+            let probe = guess.saturating_add(stride).min(max);
+            if probe == max || predicate(probe)? {
+                high = probe;
+                break;
+            }
+            low = probe + 1;
+            stride = stride.saturating_mul(2);
+        }
+    }
+
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match predicate(mid)? {
+            true => high = mid,
+            false => low = mid + 1,
+        }
+    }
+    Ok(low)
+}
+
+/// One candidate split of a single-sided deposit, with the reserves after its swap.
+struct ZapSplit {
+    swap: SwapQuote,
+    reserve_in: u64,
+    reserve_out: u64,
+    lp_in: u64,
+    lp_out: u64,
+}
+
+impl ZapSplit {
+    fn lp_amount(&self) -> u64 {
+        self.lp_in.min(self.lp_out)
+    }
+}
+
+/// A single-sided withdrawal: burning LP pays out `withdraw_in` and `withdraw_out`, then
+/// `swap` sells `withdraw_in` back to the pool so only the output token, `amount_out` in
+/// total, leaves it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZapOutQuote {
+    pub withdraw_in: u64,
+    pub withdraw_out: u64,
+    pub swap: SwapQuote,
+    pub amount_out: u64,
+}
+
+/// Quotes burning `lp_amount` for the output token only, against a pool whose LP mint has
+/// `lp_supply` outstanding. The input side's share is priced against the reserves left
+/// after the withdrawal; `curve` is seen from the input side.
+pub fn quote_zap_out(
+    curve: Curve,
+    reserve_in: u64,
+    reserve_out: u64,
+    lp_supply: u64,
+    lp_amount: u64,
+    fee: u16,
+) -> Result<ZapOutQuote> {
+    let (withdraw_in, withdraw_out) = quote_withdraw(reserve_in, reserve_out, lp_supply, lp_amount)?;
+    let reserve_in = reserve_in.checked_sub(withdraw_in).ok_or(MathError::Underflow)?;
+    let reserve_out = reserve_out.checked_sub(withdraw_out).ok_or(MathError::Underflow)?;
+    let swap = quote_swap_exact_in(curve, reserve_in, reserve_out, withdraw_in, fee)?;
+    let amount_out = withdraw_out.checked_add(swap.amount_out).ok_or(MathError::Overflow)?;
+
+    Ok(ZapOutQuote { withdraw_in, withdraw_out, swap, amount_out })
+}
+
+/// Liquidity a new pool starts with, MINIMUM_LIQUIDITY included: sqrt(m · n), or the
+/// weighted invariant for weighted pools. `curve` is seen from the M side.
+pub fn quote_initial_liquidity(curve: Curve, amount_m: u64, amount_n: u64) -> Result<u64> {
+    match curve {
+        Curve::Weighted { weight_in, weight_out } => weighted_invariant(amount_m, amount_n, weight_in, weight_out),
+        _ => {
+            let product = (amount_m as u128)
+                .checked_mul(amount_n as u128)
+                .ok_or(MathError::Overflow)?;
+            Ok(integer_sqrt(product) as u64)
+        }
+    }
+}
+
+/// Marginal price of the input token in units of the output token, as a Q64.64 number.
+pub fn spot_price(curve: Curve, reserve_in: u64, reserve_out: u64) -> Result<u128> {
+    match curve {
+        Curve::ConstantProduct => q64_price(reserve_out, reserve_in),
+        Curve::StableSwap { amp } => stable_spot_price(reserve_out, reserve_in, amp),
+        Curve::Weighted { weight_in, weight_out } => weighted_spot_price(reserve_out, reserve_in, weight_out, weight_in),
+    }
+}
+
+/// Marginal price of the input token once `quote` has executed against the reserves.
+pub fn spot_price_after(curve: Curve, reserve_in: u64, reserve_out: u64, quote: &SwapQuote) -> Result<u128> {
+    let reserve_in = reserve_in.checked_add(quote.amount_in).ok_or(MathError::Overflow)?;
+    let reserve_out = reserve_out.checked_sub(quote.amount_out).ok_or(MathError::Underflow)?;
+    spot_price(curve, reserve_in, reserve_out)
+}
+
+/// How far `quote` executes below the spot price, in basis points rounded down:
+/// 1 − amount_out / (amount_in · spot price). The fee is included, so even the smallest
+/// trade shows an impact of about the fee.
+pub fn price_impact_bps(curve: Curve, reserve_in: u64, reserve_out: u64, quote: &SwapQuote) -> Result<u64> {
+    let price = spot_price(curve, reserve_in, reserve_out)?;
+    let value_at_spot = mul_div(quote.amount_in as u128, price, Q64, false)?;
+    if value_at_spot == 0 {
+        return Ok(0);
+    }
+
+    let shortfall = value_at_spot.saturating_sub(quote.amount_out as u128);
+    Ok(mul_div(shortfall, FEE_DENOMINATOR, value_at_spot, false)? as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Curve; 3] = [
+        Curve::ConstantProduct,
+        Curve::StableSwap { amp: 100 },
+        Curve::Weighted { weight_in: 8_000, weight_out: 2_000 },
+    ];
+    const RESERVE_IN: u64 = 1_000_000_000;
+    const RESERVE_OUT: u64 = 2_000_000_000;
+    const FEE: u16 = 30;
+
+    #[test]
+    fn exact_in_and_exact_out_round_trip_in_favour_of_the_pool() {
+        for curve in CURVES {
+            for amount in [1_000, 1_000_000, 100_000_000] {
+                // Paying exactly what exact-out asks for must buy at least the requested output
+                let exact_out = quote_swap_exact_out(curve, RESERVE_IN, RESERVE_OUT, amount, FEE).unwrap();
+                let exact_in = quote_swap_exact_in(curve, RESERVE_IN, RESERVE_OUT, exact_out.amount_in, FEE).unwrap();
+                assert!(exact_in.amount_out >= amount, "{curve:?}: {} < {amount}", exact_in.amount_out);
+
+                // Asking for what exact-in paid out must never cost more than the exact-in input
+                let exact_in = quote_swap_exact_in(curve, RESERVE_IN, RESERVE_OUT, amount, FEE).unwrap();
+                let exact_out = quote_swap_exact_out(curve, RESERVE_IN, RESERVE_OUT, exact_in.amount_out, FEE).unwrap();
+                assert!(exact_out.amount_in <= amount, "{curve:?}: {} > {amount}", exact_out.amount_in);
+            }
+        }
+    }
+
+    #[test]
+    fn constant_product_rounds_output_down_and_input_up() {
+        let amount_in = 1_000_003;
+        let after_fee = amount_in as u128 - fee_amount(amount_in, FEE).unwrap() as u128;
+        let numerator = RESERVE_OUT as u128 * after_fee;
+        let denominator = RESERVE_IN as u128 + after_fee;
+        let quote = quote_swap_exact_in(Curve::ConstantProduct, RESERVE_IN, RESERVE_OUT, amount_in, FEE).unwrap();
+        assert_eq!(quote.amount_out as u128, numerator / denominator);
+
+        // One unit less than the exact-out input must fall short of the requested output
+        let quote = quote_swap_exact_out(Curve::ConstantProduct, RESERVE_IN, RESERVE_OUT, 1_999_999, FEE).unwrap();
+        let short = quote_swap_exact_in(Curve::ConstantProduct, RESERVE_IN, RESERVE_OUT, quote.amount_in - 1, FEE).unwrap();
+        assert!(short.amount_out < 1_999_999);
+    }
+
+    #[test]
+    fn exact_out_rejects_draining_the_pool() {
+        for curve in CURVES {
+            assert!(quote_swap_exact_out(curve, RESERVE_IN, RESERVE_OUT, RESERVE_OUT, FEE).is_err());
+        }
+    }
+
+    #[test]
+    fn deposits_round_up_and_withdrawals_round_down() {
+        // 7 of 3_000 LP (MINIMUM_LIQUIDITY included) is 2.335 of M and 4.676 of N
+        assert_eq!(quote_deposit(1_001, 2_004, 2_000, 7).unwrap(), (3, 5));
+        assert_eq!(quote_withdraw(1_001, 2_004, 2_000, 7).unwrap(), (2, 4));
+
+        // Exact shares are not rounded either way
+        assert_eq!(quote_deposit(3_000, 6_000, 2_000, 10).unwrap(), (10, 20));
+        assert_eq!(quote_withdraw(3_000, 6_000, 2_000, 10).unwrap(), (10, 20));
+    }
+
+    #[test]
+    fn price_impact_is_zero_for_nothing_and_grows_with_size() {
+        for curve in CURVES {
+            let nothing = quote_swap_exact_in(curve, RESERVE_IN, RESERVE_OUT, 0, FEE).unwrap();
+            assert_eq!(price_impact_bps(curve, RESERVE_IN, RESERVE_OUT, &nothing).unwrap(), 0);
+
+            // A small trade only pays about the fee
+            let small = quote_swap_exact_in(curve, RESERVE_IN, RESERVE_OUT, 10_000, FEE).unwrap();
+            let small_impact = price_impact_bps(curve, RESERVE_IN, RESERVE_OUT, &small).unwrap();
+            assert!((FEE as u64 - 1..=FEE as u64 + 1).contains(&small_impact), "{curve:?}: {small_impact}");
+
+            // A trade as large as the input reserve moves the price far beyond the fee
+            let large = quote_swap_exact_in(curve, RESERVE_IN, RESERVE_OUT, RESERVE_IN, FEE).unwrap();
+            let large_impact = price_impact_bps(curve, RESERVE_IN, RESERVE_OUT, &large).unwrap();
+            assert!(large_impact > small_impact && large_impact < 10_000, "{curve:?}: {large_impact}");
+        }
+
+        // Constant product: selling the whole input reserve again halves the price, so about half is lost
+        let large = quote_swap_exact_in(Curve::ConstantProduct, RESERVE_IN, RESERVE_OUT, RESERVE_IN, 0).unwrap();
+        assert_eq!(price_impact_bps(Curve::ConstantProduct, RESERVE_IN, RESERVE_OUT, &large).unwrap(), 5_000);
+    }
+
+    #[test]
+    fn search_from_finds_the_crossing_from_any_guess() {
+        for crossing in [0u64, 1, 37, 500_000, 999_999, 1_000_000] {
+            for guess in [0, 1, crossing.saturating_sub(3), crossing, crossing + 3, 999_999, 1_000_000, u64::MAX] {
+                let found = search_from(guess, 1_000_000, |x| Ok(x >= crossing)).unwrap();
+                assert_eq!(found, crossing, "guess {guess}");
+            }
+        }
+    }
+
+    #[test]
+    fn swaps_reject_fees_of_100_percent_or_more() {
+        for curve in CURVES {
+            for fee in [10_000, 10_001, u16::MAX] {
+                assert_eq!(quote_swap_exact_in(curve, RESERVE_IN, RESERVE_OUT, 1_000, fee), Err(MathError::InvalidFee));
+                assert_eq!(quote_swap_exact_in(curve, RESERVE_IN, RESERVE_OUT, 0, fee), Err(MathError::InvalidFee));
+                assert!(quote_swap_exact_out(curve, RESERVE_IN, RESERVE_OUT, 1_000, fee).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn zap_in_rejects_a_full_fee() {
+        let zap = quote_zap_in(Curve::ConstantProduct, RESERVE_IN, RESERVE_OUT, RESERVE_IN, 1_000, 10_000, 0);
+        assert_eq!(zap, Err(MathError::InvalidFee));
+    }
+}
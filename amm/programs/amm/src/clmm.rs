@@ -0,0 +1,48 @@
+//! Concentrated-liquidity math from the `amm-math` crate, with its errors mapped onto AmmError.
+
+use anchor_lang::prelude::*;
+use amm_math::clmm::SwapStep;
+use crate::errors::math_error;
+
+pub use amm_math::clmm::{MAX_TICK, MIN_TICK, Q64};
+
+/// a · b / denominator with a 256-bit intermediate, for results that fit in a u128.
+pub fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> Result<u128> {
+    amm_math::clmm::mul_div(a, b, denominator, round_up).map_err(math_error)
+}
+
+/// Sqrt price at a tick, as a Q64.64 number.
+pub fn sqrt_price_at_tick(tick: i32) -> Result<u128> {
+    amm_math::clmm::sqrt_price_at_tick(tick).map_err(math_error)
+}
+
+/// Greatest tick whose sqrt price does not exceed `sqrt_price`.
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> Result<i32> {
+    amm_math::clmm::tick_at_sqrt_price(sqrt_price).map_err(math_error)
+}
+
+/// Tokens M and N backing `liquidity` over [tick_lower, tick_upper) at the current price.
+pub fn amounts_for_liquidity(
+    tick_current: i32,
+    sqrt_price: u128,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<(u64, u64)> {
+    amm_math::clmm::amounts_for_liquidity(tick_current, sqrt_price, tick_lower, tick_upper, liquidity, round_up)
+        .map_err(math_error)
+}
+
+/// Prices one exact-input step of a concentrated-liquidity swap.
+pub fn compute_swap_step(
+    is_m: bool,
+    sqrt_price: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u64,
+    fee: u16,
+) -> Result<SwapStep> {
+    amm_math::clmm::compute_swap_step(is_m, sqrt_price, sqrt_price_target, liquidity, amount_remaining, fee)
+        .map_err(math_error)
+}
@@ -1,4 +1,33 @@
-/// To protect the pool from unfair advantages, a small amount of liquidity is always
-/// minted and sent to a "dead" address when the pool is first created.
-/// This ensures the first person to deposit doesn't receive excessive LP tokens.
-pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+pub use amm_math::curve::MINIMUM_LIQUIDITY;
+
+/// Minimum delay, in seconds, between queuing a fee change and it taking effect,
+/// so LPs and traders get warning before the pool's pricing changes.
+pub const FEE_UPDATE_DELAY: i64 = 86_400;
+
+/// Bounds on the StableSwap amplification coefficient.
+pub const MIN_AMP: u64 = 1;
+pub const MAX_AMP: u64 = 1_000_000;
+
+/// An amp ramp must last at least this many seconds and may change the
+/// coefficient by at most MAX_AMP_CHANGE times in either direction.
+pub const MIN_AMP_RAMP_DURATION: i64 = 86_400;
+pub const MAX_AMP_CHANGE: u64 = 10;
+
+/// Weights are expressed in basis points, so the two sides of a pool sum to WEIGHT_DENOMINATOR.
+pub const WEIGHT_DENOMINATOR: u16 = 10_000;
+
+/// Smallest weight, in basis points, either side of a weighted pool may carry.
+pub const MIN_WEIGHT: u16 = 100;
+
+/// Dynamic fees: the volatility measure halves every VOLATILITY_HALF_LIFE seconds, and every
+/// VOLATILITY_FEE_DIVISOR parts per million of it add one basis point to the base fee,
+/// so a recent 1% move raises the fee by 0.25%.
+pub const VOLATILITY_HALF_LIFE: i64 = 300;
+pub const VOLATILITY_FEE_DIVISOR: u64 = 400;
+
+/// Most fee tiers the protocol config can allow at once.
+pub const MAX_FEE_TIERS: usize = 8;
+
+/// Most observation slots a pool's oracle can hold. The whole account then fits in the
+/// 10 KiB a single instruction may allocate, so creating or growing it never hits that limit.
+pub const MAX_ORACLE_CARDINALITY: u16 = 254;
@@ -0,0 +1,79 @@
+//! Pool pricing lives in the `amm-math` crate, which off-chain clients share so their quotes
+//! match execution to the unit. These wrappers map its errors onto AmmError.
+
+use anchor_lang::prelude::*;
+use crate::errors::math_error;
+
+pub use amm_math::curve::{protocol_fee_amount, FEE_DENOMINATOR};
+pub use amm_math::{Curve, SwapQuote, ZapInQuote, ZapOutQuote};
+
+/// Portion of `amount_in` kept as the swap fee, i.e. what is not priced by the curve.
+pub fn fee_amount(amount_in: u64, fee: u16) -> Result<u64> {
+    amm_math::curve::fee_amount(amount_in, fee).map_err(math_error)
+}
+
+/// Converts a token amount into the LP it is worth against one reserve, rounded down.
+pub fn token_to_lp(amount: u64, reserve: u64, total_lp: u64) -> Result<u64> {
+    amm_math::curve::token_to_lp(amount, reserve, total_lp).map_err(math_error)
+}
+
+/// Total LP backing the reserves, including the locked MINIMUM_LIQUIDITY share.
+pub fn total_lp_supply(minted_supply: u64) -> Result<u64> {
+    amm_math::curve::total_lp_supply(minted_supply).map_err(math_error)
+}
+
+/// Quotes a swap of exactly `amount_in` under `curve`, after the fee.
+pub fn quote_swap_exact_in(curve: Curve, reserve_in: u64, reserve_out: u64, amount_in: u64, fee: u16) -> Result<SwapQuote> {
+    amm_math::quote_swap_exact_in(curve, reserve_in, reserve_out, amount_in, fee).map_err(math_error)
+}
+
+/// Quotes the input, fee included, needed to pay out exactly `amount_out` under `curve`.
+pub fn quote_swap_exact_out(curve: Curve, reserve_in: u64, reserve_out: u64, amount_out: u64, fee: u16) -> Result<SwapQuote> {
+    amm_math::quote_swap_exact_out(curve, reserve_in, reserve_out, amount_out, fee).map_err(math_error)
+}
+
+/// Tokens charged, rounded up, to mint `lp_amount`.
+pub fn quote_deposit(reserve_m: u64, reserve_n: u64, lp_supply: u64, lp_amount: u64) -> Result<(u64, u64)> {
+    amm_math::quote_deposit(reserve_m, reserve_n, lp_supply, lp_amount).map_err(math_error)
+}
+
+/// Tokens paid out, rounded down, for burning `lp_amount`.
+pub fn quote_withdraw(reserve_m: u64, reserve_n: u64, lp_supply: u64, lp_amount: u64) -> Result<(u64, u64)> {
+    amm_math::quote_withdraw(reserve_m, reserve_n, lp_supply, lp_amount).map_err(math_error)
+}
+
+/// Quotes depositing `amount_in` of one token only: the swap into the other token and the
+/// LP minted for depositing both.
+pub fn quote_zap_in(
+    curve: Curve,
+    reserve_in: u64,
+    reserve_out: u64,
+    lp_supply: u64,
+    amount_in: u64,
+    fee: u16,
+    protocol_fee: u16,
+) -> Result<ZapInQuote> {
+    amm_math::quote_zap_in(curve, reserve_in, reserve_out, lp_supply, amount_in, fee, protocol_fee).map_err(math_error)
+}
+
+/// Quotes burning `lp_amount` for one token only, selling the other side's share back to the pool.
+pub fn quote_zap_out(
+    curve: Curve,
+    reserve_in: u64,
+    reserve_out: u64,
+    lp_supply: u64,
+    lp_amount: u64,
+    fee: u16,
+) -> Result<ZapOutQuote> {
+    amm_math::quote_zap_out(curve, reserve_in, reserve_out, lp_supply, lp_amount, fee).map_err(math_error)
+}
+
+/// Liquidity a new pool starts with, MINIMUM_LIQUIDITY included.
+pub fn quote_initial_liquidity(curve: Curve, amount_m: u64, amount_n: u64) -> Result<u64> {
+    amm_math::quote_initial_liquidity(curve, amount_m, amount_n).map_err(math_error)
+}
+
+/// Marginal price of the input token in units of the output token, as a Q64.64 number.
+pub fn spot_price(curve: Curve, reserve_in: u64, reserve_out: u64) -> Result<u128> {
+    amm_math::spot_price(curve, reserve_in, reserve_out).map_err(math_error)
+}
@@ -1,3 +1,5 @@
+use amm_math::MathError;
+use anchor_lang::error::Error;
 use anchor_lang::error_code;
 #[error_code]
 pub enum AmmError {
@@ -55,4 +57,66 @@ pub enum AmmError {
     PoolNotInitialized,
     #[msg("Initial liquidity too low. Must be greater than MINIMUM_LIQUIDITY.")]
     InsufficientInitialLiquidity,
+    #[msg("Fee update timelock has not expired yet.")]
+    FeeTimelockNotExpired,
+    #[msg("No pending fee update.")]
+    NoPendingFee,
+    #[msg("Requested observation is older than the oldest stored observation.")]
+    ObservationTooOld,
+    #[msg("Invalid oracle cardinality.")]
+    InvalidCardinality,
+    #[msg("Not supported by this pool's curve.")]
+    InvalidCurve,
+    #[msg("Invalid amplification coefficient.")]
+    InvalidAmp,
+    #[msg("Invalid amplification ramp.")]
+    InvalidAmpRamp,
+    #[msg("Invalid pool weight.")]
+    InvalidWeight,
+    #[msg("Invalid tick or tick range.")]
+    InvalidTick,
+    #[msg("Invalid sqrt price.")]
+    InvalidSqrtPrice,
+    #[msg("Tick array does not belong to this pool or range.")]
+    InvalidTickArray,
+    #[msg("Swap ran past the provided tick arrays.")]
+    TickArraysExhausted,
+    #[msg("Route accounts are malformed or its hops do not chain.")]
+    InvalidRoute,
+    #[msg("Flash loan was not repaid with its fee.")]
+    FlashLoanNotRepaid,
+    #[msg("Flash loan receiver cannot be this program.")]
+    InvalidReceiver,
+    #[msg("Pool mints must be ordered with mint_m below mint_n.")]
+    InvalidMintOrder,
+    #[msg("The protocol is paused.")]
+    ProtocolPaused,
+    #[msg("Fee is not one of the protocol's fee tiers.")]
+    InvalidFeeTier,
+    #[msg("Fee tiers must be between 1 and 9999 basis points and strictly increasing.")]
+    InvalidFeeTiers,
+    #[msg("The pool's oracle must be passed once it is initialized.")]
+    OracleRequired,
+    #[msg("Oracle cardinality exceeds the maximum.")]
+    OracleCardinalityTooLarge,
+}
+
+impl From<MathError> for AmmError {
+    fn from(error: MathError) -> Self {
+        match error {
+            MathError::Overflow => AmmError::Overflow,
+            MathError::Underflow => AmmError::Underflow,
+            MathError::NoLiquidityInPool => AmmError::NoLiquidityInPool,
+            MathError::PoolNotInitialized => AmmError::PoolNotInitialized,
+            MathError::InvalidFee => AmmError::InvalidFee,
+            MathError::CurveError => AmmError::CurveError,
+            MathError::InvalidTick => AmmError::InvalidTick,
+            MathError::InvalidSqrtPrice => AmmError::InvalidSqrtPrice,
+        }
+    }
+}
+
+/// Maps a failure of the shared pool math onto this program's error codes.
+pub fn math_error(error: MathError) -> Error {
+    AmmError::from(error).into()
 }
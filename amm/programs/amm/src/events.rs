@@ -0,0 +1,229 @@
+//! Events emitted through `emit_cpi!`. They are recorded as self-invocation instruction data,
+//! so indexers can follow pool history without relying on program logs, which get truncated.
+//!
+//! Reserves are vault balances less accrued protocol fees, as returned by `Config::reserves`.
+
+use anchor_lang::prelude::*;
+use crate::state::{Config, CurveType, DynamicFee, ProtocolConfig};
+
+#[event]
+pub struct PoolInitialized {
+    pub config: Pubkey,
+    pub initializer: Pubkey,
+    pub authority: Pubkey,
+    pub mint_m: Pubkey,
+    pub mint_n: Pubkey,
+    pub curve: CurveType,
+    pub fee: u16,
+    /// Initial sqrt price for concentrated pools, 0 otherwise
+    pub sqrt_price_x64: u128,
+    /// Seed liquidity that reached the vaults; concentrated pools start empty
+    pub reserve_m: u64,
+    pub reserve_n: u64,
+    /// LP minted to the initializer, excluding MINIMUM_LIQUIDITY
+    pub lp_minted: u64,
+}
+
+#[event]
+pub struct Swap {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// Token M was paid in when set
+    pub is_m: bool,
+    /// Input that reached the vault, after any transfer fee
+    pub amount_in: u64,
+    /// Output that left the vault, before any transfer fee
+    pub amount_out: u64,
+    /// Pool fee taken out of `amount_in`, including the protocol's share
+    pub fee: u64,
+    pub protocol_fee: u64,
+    pub reserve_m_before: u64,
+    pub reserve_n_before: u64,
+    pub reserve_m_after: u64,
+    pub reserve_n_after: u64,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// Range position credited, for concentrated pools
+    pub position: Option<Pubkey>,
+    /// Amounts that reached the vaults
+    pub amount_m: u64,
+    pub amount_n: u64,
+    /// LP tokens minted, or range liquidity added for concentrated pools
+    pub liquidity: u128,
+    pub reserve_m_before: u64,
+    pub reserve_n_before: u64,
+    pub reserve_m_after: u64,
+    pub reserve_n_after: u64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub config: Pubkey,
+    pub user: Pubkey,
+    /// Range position debited, for concentrated pools
+    pub position: Option<Pubkey>,
+    /// Amounts that left the vaults, before any transfer fee
+    pub amount_m: u64,
+    pub amount_n: u64,
+    /// LP tokens burned, or range liquidity removed for concentrated pools
+    pub liquidity: u128,
+    pub reserve_m_before: u64,
+    pub reserve_n_before: u64,
+    pub reserve_m_after: u64,
+    pub reserve_n_after: u64,
+}
+
+/// The pool's fee settings after any of them changed.
+#[event]
+pub struct FeeUpdated {
+    pub config: Pubkey,
+    /// Authority that made the change, or None when a queued fee was applied
+    pub authority: Option<Pubkey>,
+    pub fee: u16,
+    pub pending_fee: Option<u16>,
+    pub fee_effective_at: i64,
+    pub protocol_fee: u16,
+    pub treasury: Pubkey,
+    pub pending_protocol_fee: Option<u16>,
+    pub pending_treasury: Option<Pubkey>,
+    pub dynamic_fee: bool,
+    pub fee_min: u16,
+    pub fee_max: u16,
+    pub pending_dynamic_fee: Option<DynamicFee>,
+}
+
+impl FeeUpdated {
+    pub fn new(config: &Account<Config>, authority: Option<Pubkey>) -> Self {
+        Self {
+            config: config.key(),
+            authority,
+            fee: config.fee,
+            pending_fee: config.pending_fee,
+            fee_effective_at: config.fee_effective_at,
+            protocol_fee: config.protocol_fee,
+            treasury: config.treasury,
+            pending_protocol_fee: config.pending_protocol_fee,
+            pending_treasury: config.pending_treasury,
+            dynamic_fee: config.dynamic_fee,
+            fee_min: config.fee_min,
+            fee_max: config.fee_max,
+            pending_dynamic_fee: config.pending_dynamic_fee,
+        }
+    }
+}
+
+#[event]
+pub struct ProtocolFeesClaimed {
+    pub config: Pubkey,
+    pub treasury: Pubkey,
+    /// Amounts that left the vaults, before any transfer fee
+    pub amount_m: u64,
+    pub amount_n: u64,
+}
+
+#[event]
+pub struct FeesCollected {
+    pub config: Pubkey,
+    pub owner: Pubkey,
+    pub position: Pubkey,
+    /// Amounts that left the vaults, before any transfer fee
+    pub amount_m: u64,
+    pub amount_n: u64,
+}
+
+#[event]
+pub struct FlashLoan {
+    pub config: Pubkey,
+    pub borrower: Pubkey,
+    pub receiver: Pubkey,
+    pub amount_m: u64,
+    pub amount_n: u64,
+    /// Fees the loan had to repay on top of each amount; they stay in the pool for LPs
+    pub fee_m: u64,
+    pub fee_n: u64,
+    /// The treasury's share of each fee, set aside from the reserves
+    pub protocol_fee_m: u64,
+    pub protocol_fee_n: u64,
+}
+
+/// A StableSwap pool's amplification ramp after it was started or stopped.
+#[event]
+pub struct AmpUpdated {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    pub amp_initial: u64,
+    pub amp_target: u64,
+    pub amp_ramp_start_ts: i64,
+    pub amp_ramp_end_ts: i64,
+}
+
+impl AmpUpdated {
+    pub fn new(config: &Account<Config>, authority: Pubkey) -> Self {
+        Self {
+            config: config.key(),
+            authority,
+            amp_initial: config.amp_initial,
+            amp_target: config.amp_target,
+            amp_ramp_start_ts: config.amp_ramp_start_ts,
+            amp_ramp_end_ts: config.amp_ramp_end_ts,
+        }
+    }
+}
+
+/// The pool's authority and pending authority after a handover step or a renounce.
+#[event]
+pub struct AuthorityUpdated {
+    pub config: Pubkey,
+    /// None once the pool is renounced
+    pub authority: Option<Pubkey>,
+    pub pending_authority: Option<Pubkey>,
+}
+
+impl AuthorityUpdated {
+    pub fn new(config: &Account<Config>) -> Self {
+        Self {
+            config: config.key(),
+            authority: config.authority,
+            pending_authority: config.pending_authority,
+        }
+    }
+}
+
+#[event]
+pub struct PoolLocked {
+    pub config: Pubkey,
+    pub authority: Pubkey,
+    /// False when the pool was unlocked
+    pub locked: bool,
+}
+
+/// The program-wide settings after any of them changed, including on creation and through
+/// an admin handover.
+#[event]
+pub struct ProtocolUpdated {
+    pub protocol: Pubkey,
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    pub fee_tiers: Vec<u16>,
+    pub default_protocol_fee: u16,
+    pub treasury: Pubkey,
+    pub paused: bool,
+}
+
+impl ProtocolUpdated {
+    pub fn new(protocol: &Account<ProtocolConfig>) -> Self {
+        Self {
+            protocol: protocol.key(),
+            admin: protocol.admin,
+            pending_admin: protocol.pending_admin,
+            fee_tiers: protocol.fee_tiers.clone(),
+            default_protocol_fee: protocol.default_protocol_fee,
+            treasury: protocol.treasury,
+            paused: protocol.paused,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+use crate::constants::{MAX_AMP, MAX_AMP_CHANGE, MIN_AMP, MIN_AMP_RAMP_DURATION};
+use crate::errors::AmmError;
+use crate::events::AmpUpdated;
+use crate::state::CurveType;
+use crate::instructions::Update;
+
+/// Starts moving a StableSwap pool's amplification linearly from its current value to
+/// `amp_target`, reached at `ramp_end_ts`. Ramps are slow and bounded so the curve
+/// cannot be reshaped underneath traders in a single block.
+pub fn ramp_amp(ctx: Context<Update>, amp_target: u64, ramp_end_ts: i64) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    require!(ctx.accounts.config.curve == CurveType::StableSwap, AmmError::InvalidCurve);
+    require!((MIN_AMP..=MAX_AMP).contains(&amp_target), AmmError::InvalidAmp);
+
+    let now = Clock::get()?.unix_timestamp;
+    let earliest = now
+        .checked_add(MIN_AMP_RAMP_DURATION)
+        .ok_or(AmmError::Overflow)?;
+    require!(ramp_end_ts >= earliest, AmmError::InvalidAmpRamp);
+
+    let amp = ctx.accounts.config.amp(now);
+    require!(
+        amp_target <= amp.saturating_mul(MAX_AMP_CHANGE) && amp <= amp_target.saturating_mul(MAX_AMP_CHANGE),
+        AmmError::InvalidAmpRamp
+    );
+
+    let config = &mut ctx.accounts.config;
+    config.amp_initial = amp;
+    config.amp_target = amp_target;
+    config.amp_ramp_start_ts = now;
+    config.amp_ramp_end_ts = ramp_end_ts;
+    emit_cpi!(AmpUpdated::new(&ctx.accounts.config, ctx.accounts.authority.key()));
+
+    Ok(())
+}
+
+/// Freezes the amplification at its current value, cancelling any ramp in progress.
+pub fn stop_ramp_amp(ctx: Context<Update>) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    require!(ctx.accounts.config.curve == CurveType::StableSwap, AmmError::InvalidCurve);
+
+    let now = Clock::get()?.unix_timestamp;
+    let config = &mut ctx.accounts.config;
+    let amp = config.amp(now);
+    config.amp_initial = amp;
+    config.amp_target = amp;
+    config.amp_ramp_start_ts = now;
+    config.amp_ramp_end_ts = now;
+    emit_cpi!(AmpUpdated::new(&ctx.accounts.config, ctx.accounts.authority.key()));
+
+    Ok(())
+}
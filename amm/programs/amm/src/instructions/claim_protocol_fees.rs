@@ -0,0 +1,121 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::errors::AmmError;
+use crate::events::ProtocolFeesClaimed;
+use crate::state::Config;
+use crate::utils::transfer_tokens;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimProtocolFees<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: only used as the owner of the treasury token accounts, pinned to `config.treasury`
+    #[account(address = config.treasury @ AmmError::InvalidAuthority)]
+    pub treasury: UncheckedAccount<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint_m,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program_m,
+    )]
+    pub treasury_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint_n,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program_n,
+    )]
+    pub treasury_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> ClaimProtocolFees<'info> {
+    pub fn withdraw_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.treasury_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.treasury_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+
+        transfer_tokens(program, mint, from, to, self.config.to_account_info(), amount, decimals, &[&seeds], remaining_accounts)
+    }
+}
+
+/// Permissionless: pays everything accrued so far to the configured treasury.
+pub fn claim_protocol_fees<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ClaimProtocolFees<'info>>,
+) -> Result<()> {
+    let fees_m = ctx.accounts.config.protocol_fees_m;
+    let fees_n = ctx.accounts.config.protocol_fees_n;
+    require!(fees_m > 0 || fees_n > 0, AmmError::ZeroBalance);
+
+    ctx.accounts.config.protocol_fees_m = 0;
+    ctx.accounts.config.protocol_fees_n = 0;
+
+    if fees_m > 0 {
+        ctx.accounts.withdraw_tokens(true, fees_m, ctx.remaining_accounts)?;
+    }
+    if fees_n > 0 {
+        ctx.accounts.withdraw_tokens(false, fees_n, ctx.remaining_accounts)?;
+    }
+    emit_cpi!(ProtocolFeesClaimed {
+        config: ctx.accounts.config.key(),
+        treasury: ctx.accounts.treasury.key(),
+        amount_m: fees_m,
+        amount_n: fees_n,
+    });
+
+    Ok(())
+}
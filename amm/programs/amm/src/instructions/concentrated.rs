@@ -0,0 +1,594 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::clmm::{amounts_for_liquidity, tick_at_sqrt_price, MAX_TICK, MIN_TICK};
+use crate::constants::WEIGHT_DENOMINATOR;
+use crate::errors::AmmError;
+use crate::events::{FeesCollected, LiquidityAdded, LiquidityRemoved, PoolInitialized};
+use crate::state::{Config, CurveType, PairRegistry, ProtocolConfig, RangePosition, Tick, TickArray};
+use crate::utils::{gross_amount, net_amount, transfer_tokens};
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(seed: u64, authority: Pubkey, fee: u16)]
+pub struct InitializeConcentrated<'info> {
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mint::token_program = token_program_n,
+        constraint = mint_m.key() < mint_n.key() @ AmmError::InvalidMintOrder,
+    )]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [b"config", seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump,
+        space = Config::DISCRIMINATOR.len() + Config::INIT_SPACE
+    )]
+    pub config: Box<Account<'info, Config>>,
+    /// Claims the ordered pair and fee tier, so a second pool for them fails to initialize
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [b"pair", mint_m.key().as_ref(), mint_n.key().as_ref(), fee.to_le_bytes().as_ref()],
+        bump,
+        space = PairRegistry::DISCRIMINATOR.len() + PairRegistry::INIT_SPACE
+    )]
+    pub pair: Box<Account<'info, PairRegistry>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(start_tick_index: i32)]
+pub struct InitializeTickArray<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"tick_array", config.key().as_ref(), start_tick_index.to_le_bytes().as_ref()],
+        bump,
+        space = TickArray::DISCRIMINATOR.len() + TickArray::INIT_SPACE
+    )]
+    pub tick_array: Box<Account<'info, TickArray>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(tick_lower: i32, tick_upper: i32)]
+pub struct OpenRangePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [
+            b"range_position",
+            config.key().as_ref(),
+            owner.key().as_ref(),
+            tick_lower.to_le_bytes().as_ref(),
+            tick_upper.to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = RangePosition::DISCRIMINATOR.len() + RangePosition::INIT_SPACE
+    )]
+    pub position: Box<Account<'info, RangePosition>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ModifyLiquidity<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+    #[account(
+        mut,
+        has_one = owner @ AmmError::NotPositionOwner,
+        has_one = config @ AmmError::InvalidPool,
+    )]
+    pub position: Box<Account<'info, RangePosition>>,
+    #[account(mut, has_one = config @ AmmError::InvalidTickArray)]
+    pub tick_array_lower: Box<Account<'info, TickArray>>,
+    /// Omitted when `tick_array_lower` also holds the upper tick
+    #[account(
+        mut,
+        has_one = config @ AmmError::InvalidTickArray,
+        constraint = tick_array_upper.key() != tick_array_lower.key() @ AmmError::InvalidTickArray,
+    )]
+    pub tick_array_upper: Option<Box<Account<'info, TickArray>>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_m,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program_m,
+    )]
+    pub owner_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_n,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program_n,
+    )]
+    pub owner_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRangePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ AmmError::NotPositionOwner,
+    )]
+    pub position: Box<Account<'info, RangePosition>>,
+}
+
+impl<'info> ModifyLiquidity<'info> {
+    /// Vault balances less accrued protocol fees, as (M, N).
+    pub fn reserves(&self) -> Result<(u64, u64)> {
+        self.config.reserves(self.vault_m.amount, self.vault_n.amount)
+    }
+
+    /// Tick of the position's upper bound, from whichever array holds it.
+    fn upper_tick(&mut self) -> Result<&mut Tick> {
+        let (tick_upper, spacing) = (self.position.tick_upper, self.config.tick_spacing);
+        match self.tick_array_upper.as_mut() {
+            Some(tick_array) => tick_array.tick_mut(tick_upper, spacing),
+            None => self.tick_array_lower.tick_mut(tick_upper, spacing),
+        }
+    }
+
+    /// Applies a liquidity change to the position, its two ticks and, if the range is active,
+    /// the pool. Fees earned so far are settled at the old liquidity first. Returns the
+    /// token amounts the change is worth, rounded in favour of the pool.
+    pub fn modify_liquidity(&mut self, delta: i128) -> Result<(u64, u64)> {
+        let (tick_lower, tick_upper) = (self.position.tick_lower, self.position.tick_upper);
+        let spacing = self.config.tick_spacing;
+
+        self.tick_array_lower.tick_mut(tick_lower, spacing)?.update_liquidity(tick_lower, &self.config, delta, false)?;
+        let upper_array = match self.tick_array_upper.as_mut() {
+            Some(tick_array) => tick_array,
+            None => &mut self.tick_array_lower,
+        };
+        upper_array.tick_mut(tick_upper, spacing)?.update_liquidity(tick_upper, &self.config, delta, true)?;
+
+        let lower = *self.tick_array_lower.tick_mut(tick_lower, spacing)?;
+        let upper = *self.upper_tick()?;
+        let (inside_m, inside_n) = self.config.fee_growth_inside(tick_lower, &lower, tick_upper, &upper);
+        self.position.settle_fees(inside_m, inside_n)?;
+        self.position.liquidity = self.position.liquidity
+            .checked_add_signed(delta)
+            .ok_or(AmmError::InsufficientBalance)?;
+
+        // A tick no range refers to any more goes back to being uninitialized
+        if lower.liquidity_gross == 0 {
+            *self.tick_array_lower.tick_mut(tick_lower, spacing)? = Tick::default();
+        }
+        if upper.liquidity_gross == 0 {
+            *self.upper_tick()? = Tick::default();
+        }
+
+        if (tick_lower..tick_upper).contains(&self.config.tick_current) {
+            self.config.liquidity = self.config.liquidity
+                .checked_add_signed(delta)
+                .ok_or(AmmError::Overflow)?;
+        }
+
+        amounts_for_liquidity(
+            self.config.tick_current,
+            self.config.sqrt_price_x64,
+            tick_lower,
+            tick_upper,
+            delta.unsigned_abs(),
+            delta > 0,
+        )
+    }
+
+    /// Transfers token M (`is_m`) or token N from the owner into the matching vault.
+    pub fn deposit_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.owner_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.owner_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        transfer_tokens(program, mint, from, to, self.owner.to_account_info(), amount, decimals, &[], remaining_accounts)
+    }
+
+    /// Transfers token M (`is_m`) or token N from its vault to the owner, signed by the config PDA.
+    pub fn withdraw_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.owner_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.owner_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+
+        transfer_tokens(program, mint, from, to, self.config.to_account_info(), amount, decimals, &[&seeds], remaining_accounts)
+    }
+}
+
+/// Creates a concentrated-liquidity pool at `sqrt_price_x64`, the Q64.64 square root of the
+/// price of M in N. It starts empty: liquidity is added through range positions.
+pub fn initialize_concentrated(
+    ctx: Context<InitializeConcentrated>,
+    seed: u64,
+    authority: Pubkey,
+    fee: u16,
+    tick_spacing: u16,
+    sqrt_price_x64: u128,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    ctx.accounts.protocol.check_fee_tier(fee)?;
+    require!(tick_spacing > 0, AmmError::InvalidTick);
+    let tick_current = tick_at_sqrt_price(sqrt_price_x64)?;
+    let now = Clock::get()?.unix_timestamp;
+
+    ctx.accounts.config.set_inner(Config {
+        seed,
+        authority: Some(authority),
+        pending_authority: None,
+        mint_m: ctx.accounts.mint_m.key(),
+        mint_n: ctx.accounts.mint_n.key(),
+        fee,
+        pending_fee: None,
+        fee_effective_at: 0,
+        protocol_fee: ctx.accounts.protocol.default_protocol_fee,
+        treasury: ctx.accounts.protocol.treasury,
+        pending_protocol_fee: None,
+        pending_treasury: None,
+        protocol_fees_m: 0,
+        protocol_fees_n: 0,
+        price_m_cumulative: 0,
+        price_n_cumulative: 0,
+        last_update_ts: now,
+        oracle_initialized: false,
+        curve: CurveType::Concentrated,
+        amp_initial: 0,
+        amp_target: 0,
+        amp_ramp_start_ts: now,
+        amp_ramp_end_ts: now,
+        weight_m: WEIGHT_DENOMINATOR / 2,
+        tick_spacing,
+        tick_current,
+        sqrt_price_x64,
+        liquidity: 0,
+        fee_growth_global_m_x64: 0,
+        fee_growth_global_n_x64: 0,
+        dynamic_fee: false,
+        fee_min: 0,
+        fee_max: 0,
+        pending_dynamic_fee: None,
+        volatility: 0,
+        volatility_updated_ts: 0,
+        locked: false,
+        bump_lp: 0, // Concentrated pools have no LP mint
+        bump: ctx.bumps.config,
+    });
+
+    ctx.accounts.pair.set_inner(PairRegistry {
+        mint_m: ctx.accounts.mint_m.key(),
+        mint_n: ctx.accounts.mint_n.key(),
+        fee,
+        config: ctx.accounts.config.key(),
+        bump: ctx.bumps.pair,
+    });
+
+    emit_cpi!(PoolInitialized {
+        config: ctx.accounts.config.key(),
+        initializer: ctx.accounts.initializer.key(),
+        authority,
+        mint_m: ctx.accounts.mint_m.key(),
+        mint_n: ctx.accounts.mint_n.key(),
+        curve: CurveType::Concentrated,
+        fee,
+        sqrt_price_x64,
+        reserve_m: 0,
+        reserve_n: 0,
+        lp_minted: 0,
+    });
+
+    Ok(())
+}
+
+/// Permissionless: allocates the ticks in [start_tick_index, start_tick_index + span).
+pub fn initialize_tick_array(ctx: Context<InitializeTickArray>, start_tick_index: i32) -> Result<()> {
+    let config = &ctx.accounts.config;
+    require!(config.curve == CurveType::Concentrated, AmmError::InvalidCurve);
+    require!(
+        start_tick_index == TickArray::start_index_for(start_tick_index, config.tick_spacing),
+        AmmError::InvalidTickArray
+    );
+    let end_tick_index = start_tick_index
+        .checked_add(TickArray::span(config.tick_spacing))
+        .ok_or(AmmError::Overflow)?;
+    require!(
+        end_tick_index > MIN_TICK && start_tick_index <= MAX_TICK,
+        AmmError::InvalidTickArray
+    );
+
+    // Set field by field: the ticks are already zeroed and too large to build on the stack
+    let tick_array = &mut ctx.accounts.tick_array;
+    tick_array.config = config.key();
+    tick_array.start_tick_index = start_tick_index;
+
+    Ok(())
+}
+
+/// Opens an empty position over [tick_lower, tick_upper). Both bounds must be multiples of
+/// the pool's tick spacing.
+pub fn open_range_position(ctx: Context<OpenRangePosition>, tick_lower: i32, tick_upper: i32) -> Result<()> {
+    let config = &ctx.accounts.config;
+    require!(config.curve == CurveType::Concentrated, AmmError::InvalidCurve);
+
+    let spacing = config.tick_spacing as i32;
+    require!(
+        tick_lower < tick_upper
+            && tick_lower >= MIN_TICK
+            && tick_upper <= MAX_TICK
+            && tick_lower % spacing == 0
+            && tick_upper % spacing == 0,
+        AmmError::InvalidTick
+    );
+
+    ctx.accounts.position.set_inner(RangePosition {
+        owner: ctx.accounts.owner.key(),
+        config: config.key(),
+        tick_lower,
+        tick_upper,
+        liquidity: 0,
+        fee_growth_inside_m_last_x64: 0,
+        fee_growth_inside_n_last_x64: 0,
+        fees_owed_m: 0,
+        fees_owed_n: 0,
+        bump: ctx.bumps.position,
+    });
+
+    Ok(())
+}
+
+/// Adds `liquidity` to the position, charging the tokens it is worth at the current price.
+pub fn increase_liquidity<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ModifyLiquidity<'info>>,
+    liquidity: u128,
+    max_m: u64,
+    max_n: u64,
+    expiration: i64,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+    require!(Clock::get()?.unix_timestamp <= expiration, AmmError::OfferExpired);
+    require!(liquidity > 0, AmmError::InvalidAmount);
+
+    let (reserve_m_before, reserve_n_before) = ctx.accounts.reserves()?;
+    let delta = i128::try_from(liquidity).map_err(|_| AmmError::Overflow)?;
+    let (m, n) = ctx.accounts.modify_liquidity(delta)?;
+
+    // m and n must land in the vaults, so the owner sends enough to also cover any transfer fee
+    let m_gross = gross_amount(&ctx.accounts.mint_m.to_account_info(), m)?;
+    let n_gross = gross_amount(&ctx.accounts.mint_n.to_account_info(), n)?;
+    require!(m_gross <= max_m && n_gross <= max_n, AmmError::SlippageExceeded);
+
+    if m_gross > 0 {
+        ctx.accounts.deposit_tokens(true, m_gross, ctx.remaining_accounts)?;
+    }
+    if n_gross > 0 {
+        ctx.accounts.deposit_tokens(false, n_gross, ctx.remaining_accounts)?;
+    }
+
+    ctx.accounts.vault_m.reload()?;
+    ctx.accounts.vault_n.reload()?;
+    let (reserve_m_after, reserve_n_after) = ctx.accounts.reserves()?;
+    emit_cpi!(LiquidityAdded {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.owner.key(),
+        position: Some(ctx.accounts.position.key()),
+        amount_m: m,
+        amount_n: n,
+        liquidity,
+        reserve_m_before,
+        reserve_n_before,
+        reserve_m_after,
+        reserve_n_after,
+    });
+
+    Ok(())
+}
+
+/// Removes `liquidity` from the position and pays out the tokens it is worth at the current price.
+/// Fees earned up to now stay owed to the position until collected. Like `withdraw`, this
+/// works in locked pools, so a lock never traps LPs' funds.
+pub fn decrease_liquidity<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ModifyLiquidity<'info>>,
+    liquidity: u128,
+    min_m: u64,
+    min_n: u64,
+    expiration: i64,
+) -> Result<()> {
+    require!(Clock::get()?.unix_timestamp <= expiration, AmmError::OfferExpired);
+    require!(liquidity > 0, AmmError::InvalidAmount);
+    require!(liquidity <= ctx.accounts.position.liquidity, AmmError::InsufficientBalance);
+
+    let (reserve_m_before, reserve_n_before) = ctx.accounts.reserves()?;
+    let delta = i128::try_from(liquidity).map_err(|_| AmmError::Overflow)?;
+    let (m, n) = ctx.accounts.modify_liquidity(-delta)?;
+
+    // Slippage is checked against what the owner receives after any transfer fee
+    let m_received = net_amount(&ctx.accounts.mint_m.to_account_info(), m)?;
+    let n_received = net_amount(&ctx.accounts.mint_n.to_account_info(), n)?;
+    require!(m_received >= min_m && n_received >= min_n, AmmError::SlippageExceeded);
+
+    if m > 0 {
+        ctx.accounts.withdraw_tokens(true, m, ctx.remaining_accounts)?;
+    }
+    if n > 0 {
+        ctx.accounts.withdraw_tokens(false, n, ctx.remaining_accounts)?;
+    }
+
+    ctx.accounts.vault_m.reload()?;
+    ctx.accounts.vault_n.reload()?;
+    let (reserve_m_after, reserve_n_after) = ctx.accounts.reserves()?;
+    emit_cpi!(LiquidityRemoved {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.owner.key(),
+        position: Some(ctx.accounts.position.key()),
+        amount_m: m,
+        amount_n: n,
+        liquidity,
+        reserve_m_before,
+        reserve_n_before,
+        reserve_m_after,
+        reserve_n_after,
+    });
+
+    Ok(())
+}
+
+/// Settles the fees the position has earned and pays out everything owed.
+pub fn collect_fees<'info>(ctx: Context<'_, '_, 'info, 'info, ModifyLiquidity<'info>>) -> Result<()> {
+    if ctx.accounts.position.liquidity > 0 {
+        ctx.accounts.modify_liquidity(0)?;
+    }
+
+    let position = &mut ctx.accounts.position;
+    let (fees_m, fees_n) = (position.fees_owed_m, position.fees_owed_n);
+    require!(fees_m > 0 || fees_n > 0, AmmError::ZeroBalance);
+    position.fees_owed_m = 0;
+    position.fees_owed_n = 0;
+
+    if fees_m > 0 {
+        ctx.accounts.withdraw_tokens(true, fees_m, ctx.remaining_accounts)?;
+    }
+    if fees_n > 0 {
+        ctx.accounts.withdraw_tokens(false, fees_n, ctx.remaining_accounts)?;
+    }
+    emit_cpi!(FeesCollected {
+        config: ctx.accounts.config.key(),
+        owner: ctx.accounts.owner.key(),
+        position: ctx.accounts.position.key(),
+        amount_m: fees_m,
+        amount_n: fees_n,
+    });
+
+    Ok(())
+}
+
+/// Closes a position with no liquidity and no uncollected fees, returning the rent to the owner.
+pub fn close_range_position(ctx: Context<CloseRangePosition>) -> Result<()> {
+    let position = &ctx.accounts.position;
+    require!(
+        position.liquidity == 0 && position.fees_owed_m == 0 && position.fees_owed_n == 0,
+        AmmError::PositionNotEmpty
+    );
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, mint_to, MintTo};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::curve::{quote_deposit, token_to_lp, total_lp_supply};
+use crate::errors::AmmError;
+use crate::events::LiquidityAdded;
+use crate::state::{Config, Oracle, ProtocolConfig};
+use crate::utils::{gross_amount, transfer_tokens};
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+    #[account(
+        mut,
+        seeds = [b"liquiditypool", config.key().as_ref()],
+        bump = config.bump_lp,
+        mint::token_program = token_program_lp,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_m,
+    )]
+    pub user_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_n,
+    )]
+    pub user_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_lp,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub token_program_lp: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(
+        mut,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Option<Box<Account<'info, Oracle>>>, // Records an observation; required once the pool's oracle is initialized
+}
+
+impl<'info> Deposit<'info> {
+    pub fn deposit_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        transfer_tokens(program, mint, from, to, self.user.to_account_info(), amount, decimals, &[], remaining_accounts)
+    }
+
+    pub fn mint_lp_tokens(&self, amount: u64) -> Result<()> {
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = MintTo {
+            mint: self.mint_lp.to_account_info(),
+            to: self.user_lp.to_account_info(),
+            authority: self.config.to_account_info(),
+        };
+
+        mint_to(
+            CpiContext::new_with_signer(
+                self.token_program_lp.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            ),
+            amount,
+        )
+    }
+}
+
+pub fn deposit<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
+    amount: u64,
+    max_m: u64,
+    max_n: u64,
+    expiration: i64,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount > 0, AmmError::InvalidAmount);
+
+    let (reserve_m, reserve_n) = ctx.accounts.config.reserves(ctx.accounts.vault_m.amount, ctx.accounts.vault_n.amount)?;
+    require!(reserve_m > 0 && reserve_n > 0, AmmError::PoolNotInitialized);
+
+    // Each side is charged in proportion to the pool: reserve * amount / total_lp, rounded up
+    let (m, n) = quote_deposit(reserve_m, reserve_n, ctx.accounts.mint_lp.supply, amount)?;
+
+    // m and n must land in the vaults, so the user sends enough to also cover any transfer fee
+    let m_gross = gross_amount(&ctx.accounts.mint_m.to_account_info(), m)?;
+    let n_gross = gross_amount(&ctx.accounts.mint_n.to_account_info(), n)?;
+    require!(m_gross <= max_m && n_gross <= max_n, AmmError::SlippageExceeded);
+
+    // The LP minted must never be worth more than what was actually deposited on either side
+    let total_lp = total_lp_supply(ctx.accounts.mint_lp.supply)?;
+    let liquidity = token_to_lp(m, reserve_m, total_lp)?.min(token_to_lp(n, reserve_n, total_lp)?);
+    require!(liquidity >= amount, AmmError::LiquidityLessThanMinimum);
+
+    ctx.accounts.config.check_oracle(ctx.accounts.oracle.as_ref())?;
+    ctx.accounts.config.update_oracle(reserve_m, reserve_n, now)?;
+    if let Some(oracle) = ctx.accounts.oracle.as_mut() {
+        let info = oracle.to_account_info();
+        oracle.record(&info, &ctx.accounts.config)?;
+    }
+    ctx.accounts.deposit_tokens(true, m_gross, ctx.remaining_accounts)?;
+    ctx.accounts.deposit_tokens(false, n_gross, ctx.remaining_accounts)?;
+    ctx.accounts.mint_lp_tokens(amount)?;
+
+    ctx.accounts.vault_m.reload()?;
+    ctx.accounts.vault_n.reload()?;
+    let (reserve_m_after, reserve_n_after) = ctx.accounts.config.reserves(ctx.accounts.vault_m.amount, ctx.accounts.vault_n.amount)?;
+    emit_cpi!(LiquidityAdded {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.user.key(),
+        position: None,
+        amount_m: m,
+        amount_n: n,
+        liquidity: amount as u128,
+        reserve_m_before: reserve_m,
+        reserve_n_before: reserve_n,
+        reserve_m_after,
+        reserve_n_after,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+use crate::curve::FEE_DENOMINATOR;
+use crate::errors::AmmError;
+use crate::events::FeeUpdated;
+use crate::state::{Config, DynamicFee, ProtocolConfig};
+use crate::instructions::Update;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ApplyFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateProtocolFee<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Account<'info, ProtocolConfig>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct EffectiveFee<'info> {
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+/// Queues a new swap fee. It stays visible in `pending_fee` until it is applied,
+/// which can happen no earlier than FEE_UPDATE_DELAY seconds from now. Like the fee
+/// pools are created at, it must be one of the protocol's fee tiers.
+pub fn update_fee(ctx: Context<Update>, fee: u16, effective_at: i64) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    ctx.accounts.protocol.check_fee_tier(fee)?;
+
+    ctx.accounts.config.schedule_fee_change(effective_at, Clock::get()?.unix_timestamp)?;
+    ctx.accounts.config.pending_fee = Some(fee);
+    emit_cpi!(FeeUpdated::new(&ctx.accounts.config, Some(ctx.accounts.authority.key())));
+
+    Ok(())
+}
+
+/// Permissionless: anyone can activate the queued fee settings once their timelock has passed.
+pub fn apply_fee(ctx: Context<ApplyFee>) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    require!(config.has_pending_fee_change(), AmmError::NoPendingFee);
+    require!(
+        Clock::get()?.unix_timestamp >= config.fee_effective_at,
+        AmmError::FeeTimelockNotExpired
+    );
+
+    if let Some(fee) = config.pending_fee {
+        config.fee = fee;
+    }
+    if let Some(protocol_fee) = config.pending_protocol_fee {
+        config.protocol_fee = protocol_fee;
+    }
+    if let Some(treasury) = config.pending_treasury {
+        config.treasury = treasury;
+    }
+    if let Some(dynamic_fee) = config.pending_dynamic_fee {
+        config.dynamic_fee = dynamic_fee.enabled;
+        config.fee_min = dynamic_fee.fee_min;
+        config.fee_max = dynamic_fee.fee_max;
+    }
+    config.clear_pending_fee_change();
+    emit_cpi!(FeeUpdated::new(&ctx.accounts.config, None));
+
+    Ok(())
+}
+
+/// Queues the treasury's share of swap fees and where claimed protocol fees are paid, behind
+/// the same timelock as `update_fee`. Fees already accrued stay claimable by the new treasury.
+/// The share funds the protocol, so only the protocol admin sets it, renounced pools included.
+pub fn set_protocol_fee(ctx: Context<UpdateProtocolFee>, protocol_fee: u16, treasury: Pubkey, effective_at: i64) -> Result<()> {
+    ctx.accounts.protocol.check_admin(ctx.accounts.admin.key)?;
+    require!((protocol_fee as u128) <= FEE_DENOMINATOR, AmmError::InvalidFee);
+
+    ctx.accounts.config.schedule_fee_change(effective_at, Clock::get()?.unix_timestamp)?;
+    ctx.accounts.config.pending_protocol_fee = Some(protocol_fee);
+    ctx.accounts.config.pending_treasury = Some(treasury);
+    emit_cpi!(FeeUpdated::new(&ctx.accounts.config, Some(ctx.accounts.admin.key())));
+
+    Ok(())
+}
+
+/// Queues a switch between a fixed fee and a dynamic fee that rises with recent volatility
+/// above the base `fee`, held within [fee_min, fee_max] basis points. Like `update_fee`,
+/// it takes effect through `apply_fee` once the timelock has passed. The fee can never
+/// rise above the protocol's largest fee tier.
+pub fn set_dynamic_fee(ctx: Context<Update>, enabled: bool, fee_min: u16, fee_max: u16, effective_at: i64) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    require!(fee_min <= fee_max, AmmError::InvalidFee);
+    require!(fee_max <= ctx.accounts.protocol.max_fee_tier(), AmmError::InvalidFeeTier);
+
+    ctx.accounts.config.schedule_fee_change(effective_at, Clock::get()?.unix_timestamp)?;
+    ctx.accounts.config.pending_dynamic_fee = Some(DynamicFee { enabled, fee_min, fee_max });
+    emit_cpi!(FeeUpdated::new(&ctx.accounts.config, Some(ctx.accounts.authority.key())));
+
+    Ok(())
+}
+
+/// Returns, via return data, the swap fee in basis points a trade would be charged right now.
+pub fn effective_fee(ctx: Context<EffectiveFee>) -> Result<u16> {
+    Ok(ctx.accounts.config.effective_fee(Clock::get()?.unix_timestamp))
+}
@@ -0,0 +1,178 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::curve::{fee_amount, protocol_fee_amount};
+use crate::errors::AmmError;
+use crate::events;
+use crate::state::{Config, CurveType, Oracle, ProtocolConfig};
+use crate::utils::transfer_tokens;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    pub borrower: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Receive the loaned tokens; usually owned by the receiver program
+    #[account(mut, token::mint = mint_m, token::token_program = token_program_m)]
+    pub destination_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = mint_n, token::token_program = token_program_n)]
+    pub destination_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: any program but this one; it is invoked with the loan and must repay the vaults
+    #[account(executable, constraint = receiver.key() != crate::ID @ AmmError::InvalidReceiver)]
+    pub receiver: UncheckedAccount<'info>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    #[account(
+        mut,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Option<Box<Account<'info, Oracle>>>, // Records an observation; required once the pool's oracle is initialized
+}
+
+impl<'info> FlashLoan<'info> {
+    /// Sends `amount` of token M (`is_m`) or token N from its vault, signed by the config PDA.
+    pub fn lend_tokens(&self, is_m: bool, amount: u64, hook_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.destination_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.destination_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+
+        transfer_tokens(program, mint, from, to, self.config.to_account_info(), amount, decimals, &[&seeds], hook_accounts)
+    }
+}
+
+/// Lends `amount_m` and `amount_n` from the vaults and invokes `receiver` with `data` and
+/// `remaining_accounts`. By the time it returns the vaults must hold what they held before
+/// plus the pool's swap fee on each amount. As with swaps, the treasury's share of the fee
+/// accrues to the protocol and the rest stays in the pool for LPs. The runtime
+/// forbids the receiver from calling back into this program, so the pool cannot be traded
+/// while its reserves are lent out. The first `hook_accounts` remaining accounts go to the
+/// transfer hooks of the lent mints; the rest are passed to the receiver.
+pub fn flash_loan<'info>(
+    ctx: Context<'_, '_, 'info, 'info, FlashLoan<'info>>,
+    amount_m: u64,
+    amount_n: u64,
+    data: Vec<u8>,
+    hook_accounts: u8,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    let config = &ctx.accounts.config;
+    require!(!config.locked, AmmError::PoolLocked);
+    require!(config.curve != CurveType::Concentrated, AmmError::InvalidCurve);
+    require!(amount_m > 0 || amount_n > 0, AmmError::InvalidAmount);
+    config.check_oracle(ctx.accounts.oracle.as_ref())?;
+    require!(ctx.remaining_accounts.len() >= hook_accounts as usize, ErrorCode::AccountNotEnoughKeys);
+    let (hook_accounts, receiver_accounts) = ctx.remaining_accounts.split_at(hook_accounts as usize);
+
+    let now = Clock::get()?.unix_timestamp;
+    let fee = config.effective_fee(now);
+    let (fee_m, fee_n) = (fee_amount(amount_m, fee)?, fee_amount(amount_n, fee)?);
+    let repay_m = ctx.accounts.vault_m.amount
+        .checked_add(fee_m)
+        .ok_or(AmmError::Overflow)?;
+    let repay_n = ctx.accounts.vault_n.amount
+        .checked_add(fee_n)
+        .ok_or(AmmError::Overflow)?;
+
+    // The fee grows the reserves, so advance the TWAP at the prices held until now
+    let (reserve_m, reserve_n) = config.reserves(ctx.accounts.vault_m.amount, ctx.accounts.vault_n.amount)?;
+    ctx.accounts.config.update_oracle(reserve_m, reserve_n, now)?;
+
+    ctx.accounts.lend_tokens(true, amount_m, hook_accounts)?;
+    ctx.accounts.lend_tokens(false, amount_n, hook_accounts)?;
+
+    let receiver = ctx.accounts.receiver.to_account_info();
+    let instruction = Instruction {
+        program_id: receiver.key(),
+        accounts: receiver_accounts
+            .iter()
+            .map(|account| match account.is_writable {
+                true => AccountMeta::new(account.key(), account.is_signer),
+                false => AccountMeta::new_readonly(account.key(), account.is_signer),
+            })
+            .collect(),
+        data,
+    };
+    let mut account_infos = receiver_accounts.to_vec();
+    account_infos.push(receiver);
+    invoke(&instruction, &account_infos)?;
+
+    ctx.accounts.vault_m.reload()?;
+    ctx.accounts.vault_n.reload()?;
+    require!(
+        ctx.accounts.vault_m.amount >= repay_m && ctx.accounts.vault_n.amount >= repay_n,
+        AmmError::FlashLoanNotRepaid
+    );
+    let protocol_fee = ctx.accounts.config.protocol_fee;
+    let (protocol_fee_m, protocol_fee_n) = (protocol_fee_amount(fee_m, protocol_fee), protocol_fee_amount(fee_n, protocol_fee));
+    ctx.accounts.config.accrue_protocol_fee(true, protocol_fee_m)?;
+    ctx.accounts.config.accrue_protocol_fee(false, protocol_fee_n)?;
+    if let Some(oracle) = ctx.accounts.oracle.as_mut() {
+        let info = oracle.to_account_info();
+        oracle.record(&info, &ctx.accounts.config)?;
+    }
+    emit_cpi!(events::FlashLoan {
+        config: ctx.accounts.config.key(),
+        borrower: ctx.accounts.borrower.key(),
+        receiver: ctx.accounts.receiver.key(),
+        amount_m,
+        amount_n,
+        fee_m,
+        fee_n,
+        protocol_fee_m,
+        protocol_fee_n,
+    });
+
+    Ok(())
+}
@@ -1,19 +1,26 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_2022::{Token2022, spl_token_2022};
-use anchor_spl::token_interface::{Mint as InterfaceMint, TokenAccount as InterfaceTokenAccount, transfer, Transfer};
-use anchor_spl::token::{Mint, TokenAccount, Token, mint_to, MintTo};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, mint_to, MintTo};
 use anchor_spl::associated_token::AssociatedToken;
 use crate::errors::AmmError;
-use crate::state::Config;
-use crate::constants::MINIMUM_LIQUIDITY;
+use crate::events::PoolInitialized;
+use crate::state::{Config, CurveType, PairRegistry, ProtocolConfig};
+use crate::constants::{MAX_AMP, MINIMUM_LIQUIDITY, MIN_AMP, MIN_WEIGHT, WEIGHT_DENOMINATOR};
+use crate::curve::quote_initial_liquidity;
+use crate::utils::{net_amount, transfer_tokens};
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(seed: u64)]
+#[instruction(seed: u64, authority: Pubkey, fee: u16)]
 pub struct Initialize<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
-    pub mint_m: Box<InterfaceAccount<'info, InterfaceMint>>,
-    pub mint_n: Box<InterfaceAccount<'info, InterfaceMint>>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mint::token_program = token_program_n,
+        constraint = mint_m.key() < mint_n.key() @ AmmError::InvalidMintOrder,
+    )]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
     #[account(
         init,
         seeds = [b"liquiditypool", config.key.as_ref()],
@@ -21,41 +28,47 @@ pub struct Initialize<'info> {
         bump,
         mint::decimals = 6,
         mint::authority = config,
+        mint::token_program = token_program_lp,
     )]
-    pub mint_lp: Box<Account<'info, Mint>>,
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
     #[account(
         init,
         payer = initializer,
         associated_token::mint = mint_m,
         associated_token::authority = config,
+        associated_token::token_program = token_program_m,
     )]
-    pub vault_m: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init,
         payer = initializer,
         associated_token::mint = mint_n,
         associated_token::authority = config,
+        associated_token::token_program = token_program_n,
     )]
-    pub vault_n: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         associated_token::mint = mint_m,
         associated_token::authority = initializer,
+        associated_token::token_program = token_program_m,
     )]
-    pub initializer_m: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    pub initializer_m: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         associated_token::mint = mint_n,
         associated_token::authority = initializer,
+        associated_token::token_program = token_program_n,
     )]
-    pub initializer_n: Box<InterfaceAccount<'info, InterfaceTokenAccount>>,
+    pub initializer_n: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init,
         payer = initializer,
         associated_token::mint = mint_lp,
         associated_token::authority = initializer,
+        associated_token::token_program = token_program_lp,
     )]
-    pub initializer_lp: Box<Account<'info, TokenAccount>>,
+    pub initializer_lp: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init, 
         payer = initializer, 
@@ -64,33 +77,94 @@ pub struct Initialize<'info> {
         space =  Config::DISCRIMINATOR.len() + Config::INIT_SPACE
     )]
     pub config: Account<'info, Config>,
+    /// Claims the ordered pair and fee tier, so a second pool for them fails to initialize
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [b"pair", mint_m.key().as_ref(), mint_n.key().as_ref(), fee.to_le_bytes().as_ref()],
+        bump,
+        space = PairRegistry::DISCRIMINATOR.len() + PairRegistry::INIT_SPACE
+    )]
+    pub pair: Box<Account<'info, PairRegistry>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub token_2022_program: Option<Program<'info, Token2022>>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    /// Token program the LP mint is created under, either SPL Token or Token-2022
+    pub token_program_lp: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 impl<'info> Initialize<'info> {
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_config(
         &mut self,
         seed: u64,
         authority: Pubkey,
         fee: u16,
+        curve: CurveType,
+        amp: u64,
+        weight_m: u16,
         bumps: &InitializeBumps
     ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
         self.config.set_inner(
             Config {
                 seed,
-                authority,
+                authority: Some(authority),
+                pending_authority: None,
                 mint_m: self.mint_m.key(),
                 mint_n: self.mint_n.key(),
                 fee,
+                pending_fee: None,
+                fee_effective_at: 0,
+                protocol_fee: self.protocol.default_protocol_fee,
+                treasury: self.protocol.treasury,
+                pending_protocol_fee: None,
+                pending_treasury: None,
+                protocol_fees_m: 0,
+                protocol_fees_n: 0,
+                price_m_cumulative: 0,
+                price_n_cumulative: 0,
+                last_update_ts: now,
+                oracle_initialized: false,
+                curve,
+                amp_initial: amp,
+                amp_target: amp,
+                amp_ramp_start_ts: now,
+                amp_ramp_end_ts: now,
+                weight_m,
+                tick_spacing: 0,
+                tick_current: 0,
+                sqrt_price_x64: 0,
+                liquidity: 0,
+                fee_growth_global_m_x64: 0,
+                fee_growth_global_n_x64: 0,
+                dynamic_fee: false,
+                fee_min: 0,
+                fee_max: 0,
+                pending_dynamic_fee: None,
+                volatility: 0,
+                volatility_updated_ts: 0,
                 locked: false,
                 bump_lp: bumps.mint_lp,
                 bump: bumps.config
             }
         );
 
+        self.pair.set_inner(PairRegistry {
+            mint_m: self.mint_m.key(),
+            mint_n: self.mint_n.key(),
+            fee,
+            config: self.config.key(),
+            bump: bumps.pair,
+        });
+
         Ok(())
     }
 
@@ -98,51 +172,34 @@ impl<'info> Initialize<'info> {
         &self,
         is_x: bool,
         amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
     ) -> Result<()> {
-        let (mint, from, to) = match is_x {
+        let (program, mint, from, to, decimals) = match is_x {
             true => (
+                self.token_program_m.to_account_info(),
                 self.mint_m.to_account_info(),
                 self.initializer_m.to_account_info(),
                 self.vault_m.to_account_info(),
+                self.mint_m.decimals,
             ),
             false => (
+                self.token_program_n.to_account_info(),
                 self.mint_n.to_account_info(),
                 self.initializer_n.to_account_info(),
                 self.vault_n.to_account_info(),
+                self.mint_n.decimals,
             )
         };
 
-        let cpi_accounts = Transfer {
-            from,
-            to,
-            authority: self.initializer.to_account_info(),
-        };
-
-        let program = if *mint.owner == spl_token_2022::ID {
-            self.token_2022_program.as_ref().ok_or(AmmError::InvalidToken)?.to_account_info()
-        } else {
-            self.token_program.to_account_info()
-        };
-
-        transfer(CpiContext::new(program, cpi_accounts), amount)
+        transfer_tokens(program, mint, from, to, self.initializer.to_account_info(), amount, decimals, &[], remaining_accounts)
     }
 
     pub fn mint_initial_lp(
         &self,
         liquidity: u64,
-        bumps: &InitializeBumps,
     ) -> Result<()> {
         let seed_bytes = self.config.seed.to_le_bytes();
-        let mint_m_bytes = self.mint_m.key().to_bytes();
-        let mint_n_bytes = self.mint_n.key().to_bytes();
-
-        let seeds: &[&[u8]] = &[
-            b"config".as_ref(),
-            seed_bytes.as_ref(),
-            mint_m_bytes.as_ref(),
-            mint_n_bytes.as_ref(),
-            &[bumps.config],
-        ];
+        let seeds = self.config.signer_seeds(&seed_bytes);
         let signer_seeds = &[&seeds[..]];
 
         let cpi_accounts = MintTo {
@@ -153,7 +210,7 @@ impl<'info> Initialize<'info> {
 
         mint_to(
             CpiContext::new_with_signer(
-                self.token_program.to_account_info(),
+                self.token_program_lp.to_account_info(),
                 cpi_accounts,
                 signer_seeds,
             ),
@@ -162,49 +219,68 @@ impl<'info> Initialize<'info> {
     }
 }
 
-/// Computes the integer square root of a number using Newton's approximation method.
-fn integer_sqrt(n: u128) -> u128 {
-    if n == 0 {
-        return 0;
-    }
-    let mut x = n;
-    let mut y = (x + 1) / 2;
-    while y < x {
-        x = y;
-        y = (x + n / x) / 2;
-    }
-    x
-}
-
-pub fn initialize(
-    ctx: Context<Initialize>,
+#[allow(clippy::too_many_arguments)]
+pub fn initialize<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Initialize<'info>>,
     seed: u64,
     authority: Pubkey,
     fee: u16,
     init_m_amount: u64,
     init_n_amount: u64,
+    curve: CurveType,
+    amp: u64,
+    weight_m: u16,
 ) -> Result<()> {
-    require!(fee <= 10000, AmmError::InvalidFee);
+    ctx.accounts.protocol.check_not_paused()?;
+    ctx.accounts.protocol.check_fee_tier(fee)?;
+    // Concentrated pools start without reserves, see `initialize_concentrated`
+    require!(curve != CurveType::Concentrated, AmmError::InvalidCurve);
+
+    // The StableSwap invariant treats one unit of M as one unit of N, so both sides
+    // must share a precision. Other curves ignore `amp`.
+    let amp = match curve {
+        CurveType::StableSwap => {
+            require!((MIN_AMP..=MAX_AMP).contains(&amp), AmmError::InvalidAmp);
+            require!(ctx.accounts.mint_m.decimals == ctx.accounts.mint_n.decimals, AmmError::InvalidPrecision);
+            amp
+        }
+        _ => 0,
+    };
+
+    // Only weighted pools may skew value away from an even split
+    let weight_m = match curve {
+        CurveType::Weighted => {
+            require!(
+                (MIN_WEIGHT..=WEIGHT_DENOMINATOR - MIN_WEIGHT).contains(&weight_m),
+                AmmError::InvalidWeight
+            );
+            weight_m
+        }
+        _ => WEIGHT_DENOMINATOR / 2,
+    };
 
     require!(init_m_amount > 0, AmmError::InvalidAmount);
     require!(init_n_amount > 0, AmmError::InvalidAmount);
 
     // Set up the pool configuration before proceeding (this is required for generating signer seeds)
-    ctx.accounts.initialize_config(seed, authority, fee, &ctx.bumps)?;
+    ctx.accounts.initialize_config(seed, authority, fee, curve, amp, weight_m, &ctx.bumps)?;
 
     // Transfer initial liquidity funds from the initializer to each vault (token M and token N)
     // This will move init_m_amount of token M and init_n_amount of token N from the user to the pool's vaults
     // Deposits must succeed before proceeding to LP minting logic
-    ctx.accounts.deposit_tokens(true, init_m_amount)?;
-    ctx.accounts.deposit_tokens(false, init_n_amount)?;
+    ctx.accounts.deposit_tokens(true, init_m_amount, ctx.remaining_accounts)?;
+    ctx.accounts.deposit_tokens(false, init_n_amount, ctx.remaining_accounts)?;
+
+    // Token-2022 transfer fees mean the vaults may receive less than was sent,
+    // so LP is priced on the amounts that actually landed
+    let received_m = net_amount(&ctx.accounts.mint_m.to_account_info(), init_m_amount)?;
+    let received_n = net_amount(&ctx.accounts.mint_n.to_account_info(), init_n_amount)?;
 
     // Compute the amount of LP tokens to mint for the initial liquidity provider.
-    // Formula: liquidity = sqrt(init_m_amount * init_n_amount)
+    // Formula: liquidity = sqrt(received_m * received_n), or received_m^w_m * received_n^w_n for weighted pools
     // Initial LP tokens minted: liquidity - MINIMUM_LIQUIDITY
-    let product = (init_m_amount as u128)
-        .checked_mul(init_n_amount as u128)
-        .ok_or(AmmError::Overflow)?;
-    let liquidity = integer_sqrt(product) as u64;
+    let now = Clock::get()?.unix_timestamp;
+    let liquidity = quote_initial_liquidity(ctx.accounts.config.pricing_curve(true, now)?, received_m, received_n)?;
 
     // Ensure the pool has at least MINIMUM_LIQUIDITY to be locked for fairness
     require!(liquidity > MINIMUM_LIQUIDITY, AmmError::InsufficientInitialLiquidity);
@@ -216,10 +292,24 @@ pub fn initialize(
     // Mint LP tokens to the user who provided the initial liquidity.
     // A small, fixed amount (MINIMUM_LIQUIDITY) is permanently locked to make sure the pool stays balanced and fair,
     // so the user receives the rest of the tokens.
-    ctx.accounts.mint_initial_lp(lp_to_mint, &ctx.bumps)?;
+    ctx.accounts.mint_initial_lp(lp_to_mint)?;
 
     msg!("Initialized pool with total liquidity: {}, user minted: {}, protocol locked: {}", 
          liquidity, lp_to_mint, MINIMUM_LIQUIDITY);
 
+    emit_cpi!(PoolInitialized {
+        config: ctx.accounts.config.key(),
+        initializer: ctx.accounts.initializer.key(),
+        authority,
+        mint_m: ctx.accounts.mint_m.key(),
+        mint_n: ctx.accounts.mint_n.key(),
+        curve,
+        fee,
+        sqrt_price_x64: 0,
+        reserve_m: received_m,
+        reserve_n: received_n,
+        lp_minted: lp_to_mint,
+    });
+
     Ok(())
 }
\ No newline at end of file
@@ -1,2 +1,32 @@
+pub mod amp;
+pub use amp::*;
+pub mod claim_protocol_fees;
+pub use claim_protocol_fees::*;
+pub mod concentrated;
+pub use concentrated::*;
+pub mod deposit;
+pub use deposit::*;
+pub mod fee;
+pub use fee::*;
+pub mod flash_loan;
+pub use flash_loan::*;
 pub mod initialize;
 pub use initialize::*;
+pub mod oracle;
+pub use oracle::*;
+pub mod position;
+pub use position::*;
+pub mod protocol;
+pub use protocol::*;
+pub mod route_swap;
+pub use route_swap::*;
+pub mod swap;
+pub use swap::*;
+pub mod swap_concentrated;
+pub use swap_concentrated::*;
+pub mod update;
+pub use update::*;
+pub mod withdraw;
+pub use withdraw::*;
+pub mod zap;
+pub use zap::*;
@@ -0,0 +1,152 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
+use crate::constants::MAX_ORACLE_CARDINALITY;
+use crate::errors::AmmError;
+use crate::state::{Config, Observation, Oracle};
+
+#[derive(Accounts)]
+#[instruction(cardinality: u16)]
+pub struct InitializeOracle<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump,
+        space = Oracle::space(cardinality.min(MAX_ORACLE_CARDINALITY)), // Checked in the handler for a clear error
+    )]
+    pub oracle: Box<Account<'info, Oracle>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(cardinality_next: u16)]
+pub struct GrowOracle<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"oracle", oracle.config.as_ref()],
+        bump = oracle.bump,
+        realloc = Oracle::space(cardinality_next.min(MAX_ORACLE_CARDINALITY)), // Checked in the handler for a clear error
+        realloc::payer = payer,
+        realloc::zero = true,
+    )]
+    pub oracle: Box<Account<'info, Oracle>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Observe<'info> {
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Box<Account<'info, Oracle>>,
+    #[account(
+        associated_token::mint = config.mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        associated_token::mint = config.mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+}
+
+/// Time-weighted average prices over one lookback window, as Q64.64 fixed-point numbers.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Twap {
+    pub seconds_ago: u32,
+    pub price_m: u128, // Average price of M in N
+    pub price_n: u128, // Average price of N in M
+}
+
+/// Creates the pool's observation buffer with room for `cardinality` entries and seeds it
+/// with the current cumulative prices. From then on the pool's reserve-changing instructions
+/// require the buffer, so only the pool authority may create it.
+pub fn initialize_oracle(ctx: Context<InitializeOracle>, cardinality: u16) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    require!(cardinality > 0, AmmError::InvalidCardinality);
+    require!(cardinality <= MAX_ORACLE_CARDINALITY, AmmError::OracleCardinalityTooLarge);
+    ctx.accounts.config.oracle_initialized = true;
+
+    ctx.accounts.oracle.set_inner(Oracle {
+        config: ctx.accounts.config.key(),
+        index: 0,
+        cardinality: 1,
+        cardinality_next: cardinality,
+        bump: ctx.bumps.oracle,
+    });
+
+    let config = &ctx.accounts.config;
+    Oracle::write(&ctx.accounts.oracle.to_account_info(), 0, &Observation {
+        timestamp: config.last_update_ts,
+        price_m_cumulative: config.price_m_cumulative,
+        price_n_cumulative: config.price_n_cumulative,
+    })
+}
+
+/// Permissionless: anyone paying the rent can allocate more observation slots.
+pub fn grow_oracle(ctx: Context<GrowOracle>, cardinality_next: u16) -> Result<()> {
+    require!(cardinality_next > ctx.accounts.oracle.cardinality_next, AmmError::InvalidCardinality);
+    require!(cardinality_next <= MAX_ORACLE_CARDINALITY, AmmError::OracleCardinalityTooLarge);
+    ctx.accounts.oracle.cardinality_next = cardinality_next;
+
+    Ok(())
+}
+
+/// Returns, via return data, the TWAPs between `now - seconds_ago` and now for each lookback.
+pub fn observe(ctx: Context<Observe>, seconds_agos: Vec<u32>) -> Result<Vec<Twap>> {
+    let now = Clock::get()?.unix_timestamp;
+    let config = &ctx.accounts.config;
+    let oracle = &ctx.accounts.oracle;
+    let oracle_info = oracle.to_account_info();
+
+    let (reserve_m, reserve_n) = config.reserves(ctx.accounts.vault_m.amount, ctx.accounts.vault_n.amount)?;
+    let (now_m, now_n) = config.cumulative_prices(reserve_m, reserve_n, now)?;
+
+    // The newest exact point is the config itself; reserves have been constant since then
+    let last_update = Observation {
+        timestamp: config.last_update_ts,
+        price_m_cumulative: config.price_m_cumulative,
+        price_n_cumulative: config.price_n_cumulative,
+    };
+
+    seconds_agos
+        .into_iter()
+        .map(|seconds_ago| {
+            require!(seconds_ago > 0, AmmError::InvalidAmount);
+
+            let target = now - seconds_ago as i64;
+            let (then_m, then_n) = match target >= config.last_update_ts {
+                true => config.cumulative_prices(reserve_m, reserve_n, target)?,
+                false => oracle.cumulative_prices_at(&oracle_info, target, &last_update)?,
+            };
+
+            Ok(Twap {
+                seconds_ago,
+                price_m: now_m.wrapping_sub(then_m) / seconds_ago as u128,
+                price_n: now_n.wrapping_sub(then_n) / seconds_ago as u128,
+            })
+        })
+        .collect()
+}
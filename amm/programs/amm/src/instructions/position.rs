@@ -0,0 +1,293 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, transfer_checked, TransferChecked, close_account, CloseAccount};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::errors::AmmError;
+use crate::state::{Config, Position};
+
+#[derive(Accounts)]
+#[instruction(seed: u64)]
+pub struct CreatePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"liquiditypool", config.key().as_ref()],
+        bump = config.bump_lp,
+        mint::token_program = token_program,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    pub owner_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"position", config.key().as_ref(), owner.key().as_ref(), seed.to_le_bytes().as_ref()],
+        bump,
+        space = Position::DISCRIMINATOR.len() + Position::INIT_SPACE
+    )]
+    pub position: Box<Account<'info, Position>>,
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = mint_lp,
+        associated_token::authority = position,
+        associated_token::token_program = token_program,
+    )]
+    pub position_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"liquiditypool", config.key().as_ref()],
+        bump = config.bump_lp,
+        mint::token_program = token_program,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    pub owner_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"position", config.key().as_ref(), position.creator.as_ref(), position.seed.to_le_bytes().as_ref()],
+        bump = position.bump,
+        has_one = owner @ AmmError::NotPositionOwner,
+        has_one = config @ AmmError::InvalidPool,
+    )]
+    pub position: Box<Account<'info, Position>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = position,
+        associated_token::token_program = token_program,
+    )]
+    pub position_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"position", position.config.as_ref(), position.creator.as_ref(), position.seed.to_le_bytes().as_ref()],
+        bump = position.bump,
+        has_one = owner @ AmmError::NotPositionOwner,
+    )]
+    pub position: Account<'info, Position>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"liquiditypool", config.key().as_ref()],
+        bump = config.bump_lp,
+        mint::token_program = token_program,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"position", config.key().as_ref(), position.creator.as_ref(), position.seed.to_le_bytes().as_ref()],
+        bump = position.bump,
+        has_one = owner @ AmmError::NotPositionOwner,
+        has_one = config @ AmmError::InvalidPool,
+    )]
+    pub position: Box<Account<'info, Position>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = position,
+        associated_token::token_program = token_program,
+    )]
+    pub position_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_lp,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    pub owner_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> CreatePosition<'info> {
+    pub fn lock_lp_tokens(&self, amount: u64) -> Result<()> {
+        let cpi_accounts = TransferChecked {
+            from: self.owner_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.position_lp.to_account_info(),
+            authority: self.owner.to_account_info(),
+        };
+
+        transfer_checked(
+            CpiContext::new(self.token_program.to_account_info(), cpi_accounts),
+            amount,
+            self.mint_lp.decimals,
+        )
+    }
+}
+
+impl<'info> WithdrawPosition<'info> {
+    pub fn release_lp_tokens(&self, amount: u64) -> Result<()> {
+        let seed_bytes = self.position.seed.to_le_bytes();
+        let seeds = self.position.signer_seeds(&seed_bytes);
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: self.position_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.owner_lp.to_account_info(),
+            authority: self.position.to_account_info(),
+        };
+
+        transfer_checked(
+            CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds),
+            amount,
+            self.mint_lp.decimals,
+        )
+    }
+}
+
+impl<'info> ClosePosition<'info> {
+    /// Sweeps LP that was sent to the position from outside to the owner, so the account
+    /// can be closed.
+    pub fn sweep_position_lp(&self) -> Result<()> {
+        let seed_bytes = self.position.seed.to_le_bytes();
+        let seeds = self.position.signer_seeds(&seed_bytes);
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: self.position_lp.to_account_info(),
+            mint: self.mint_lp.to_account_info(),
+            to: self.owner_lp.to_account_info(),
+            authority: self.position.to_account_info(),
+        };
+
+        transfer_checked(
+            CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer_seeds),
+            self.position_lp.amount,
+            self.mint_lp.decimals,
+        )
+    }
+
+    pub fn close_position_lp(&self) -> Result<()> {
+        let seed_bytes = self.position.seed.to_le_bytes();
+        let seeds = self.position.signer_seeds(&seed_bytes);
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = CloseAccount {
+            account: self.position_lp.to_account_info(),
+            destination: self.owner.to_account_info(),
+            authority: self.position.to_account_info(),
+        };
+
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            signer_seeds,
+        ))
+    }
+}
+
+/// Locks `amount` LP into a new position that unlocks linearly between `start_ts` and `end_ts`,
+/// with nothing withdrawable before `cliff_ts`.
+pub fn create_position(
+    ctx: Context<CreatePosition>,
+    seed: u64,
+    amount: u64,
+    start_ts: i64,
+    cliff_ts: i64,
+    end_ts: i64,
+) -> Result<()> {
+    require!(amount > 0, AmmError::InvalidAmount);
+    require!(amount <= ctx.accounts.owner_lp.amount, AmmError::InsufficientBalance);
+    require!(
+        start_ts <= cliff_ts && cliff_ts <= end_ts && start_ts < end_ts,
+        AmmError::InvalidVestingSchedule
+    );
+
+    ctx.accounts.position.set_inner(Position {
+        owner: ctx.accounts.owner.key(),
+        config: ctx.accounts.config.key(),
+        creator: ctx.accounts.owner.key(),
+        seed,
+        total_amount: amount,
+        withdrawn_amount: 0,
+        start_ts,
+        cliff_ts,
+        end_ts,
+        bump: ctx.bumps.position,
+    });
+
+    ctx.accounts.lock_lp_tokens(amount)
+}
+
+/// Releases up to the currently unlocked amount of LP back to the position owner.
+pub fn withdraw_position(ctx: Context<WithdrawPosition>, amount: u64) -> Result<()> {
+    require!(amount > 0, AmmError::InvalidAmount);
+
+    let unlocked = ctx.accounts.position.unlocked_amount(Clock::get()?.unix_timestamp)?;
+    require!(amount <= unlocked, AmmError::InsufficientUnlockedLp);
+
+    ctx.accounts.position.withdrawn_amount = ctx.accounts.position.withdrawn_amount
+        .checked_add(amount)
+        .ok_or(AmmError::Overflow)?;
+
+    ctx.accounts.release_lp_tokens(amount)
+}
+
+/// Hands the position, and the right to its remaining LP, to a new owner.
+pub fn transfer_position(ctx: Context<TransferPosition>, new_owner: Pubkey) -> Result<()> {
+    ctx.accounts.position.owner = new_owner;
+
+    Ok(())
+}
+
+/// Closes a fully withdrawn position and its LP account, returning the rent to the owner.
+/// LP that anyone sent to the position's account since is paid out to the owner first.
+pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+    let position = &ctx.accounts.position;
+    require!(position.withdrawn_amount == position.total_amount, AmmError::PositionNotEmpty);
+
+    if ctx.accounts.position_lp.amount > 0 {
+        ctx.accounts.sweep_position_lp()?;
+    }
+    ctx.accounts.close_position_lp()
+}
@@ -0,0 +1,143 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+use crate::curve::FEE_DENOMINATOR;
+use crate::errors::AmmError;
+use crate::events::ProtocolUpdated;
+use crate::state::ProtocolConfig;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeProtocol<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"protocol"],
+        bump,
+        space = ProtocolConfig::DISCRIMINATOR.len() + ProtocolConfig::INIT_SPACE
+    )]
+    pub protocol: Account<'info, ProtocolConfig>,
+    /// Only the program's upgrade authority may create the config, so it cannot be front-run
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(payer.key()) @ AmmError::InvalidAuthority,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateProtocol<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Account<'info, ProtocolConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptProtocolAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Account<'info, ProtocolConfig>,
+}
+
+/// Creates the program-wide config. There is only one, and only the program's upgrade
+/// authority can create it.
+pub fn initialize_protocol(
+    ctx: Context<InitializeProtocol>,
+    admin: Pubkey,
+    fee_tiers: Vec<u16>,
+    default_protocol_fee: u16,
+    treasury: Pubkey,
+) -> Result<()> {
+    ProtocolConfig::validate_fee_tiers(&fee_tiers)?;
+    require!((default_protocol_fee as u128) <= FEE_DENOMINATOR, AmmError::InvalidFee);
+
+    ctx.accounts.protocol.set_inner(ProtocolConfig {
+        admin,
+        pending_admin: None,
+        fee_tiers,
+        default_protocol_fee,
+        treasury,
+        paused: false,
+        bump: ctx.bumps.protocol,
+    });
+    emit_cpi!(ProtocolUpdated::new(&ctx.accounts.protocol));
+
+    Ok(())
+}
+
+/// Replaces the fee tiers new pools may be created at. Existing pools keep their fees.
+pub fn set_fee_tiers(ctx: Context<UpdateProtocol>, fee_tiers: Vec<u16>) -> Result<()> {
+    ctx.accounts.protocol.check_admin(ctx.accounts.admin.key)?;
+    ProtocolConfig::validate_fee_tiers(&fee_tiers)?;
+
+    ctx.accounts.protocol.fee_tiers = fee_tiers;
+    emit_cpi!(ProtocolUpdated::new(&ctx.accounts.protocol));
+
+    Ok(())
+}
+
+/// Sets the protocol fee share new pools start with. Existing pools keep theirs.
+pub fn set_default_protocol_fee(ctx: Context<UpdateProtocol>, default_protocol_fee: u16) -> Result<()> {
+    ctx.accounts.protocol.check_admin(ctx.accounts.admin.key)?;
+    require!((default_protocol_fee as u128) <= FEE_DENOMINATOR, AmmError::InvalidFee);
+
+    ctx.accounts.protocol.default_protocol_fee = default_protocol_fee;
+    emit_cpi!(ProtocolUpdated::new(&ctx.accounts.protocol));
+
+    Ok(())
+}
+
+/// Sets the treasury new pools pay protocol fees to. Existing pools keep theirs until the
+/// admin moves them with `set_protocol_fee`.
+pub fn set_protocol_treasury(ctx: Context<UpdateProtocol>, treasury: Pubkey) -> Result<()> {
+    ctx.accounts.protocol.check_admin(ctx.accounts.admin.key)?;
+
+    ctx.accounts.protocol.treasury = treasury;
+    emit_cpi!(ProtocolUpdated::new(&ctx.accounts.protocol));
+
+    Ok(())
+}
+
+pub fn set_protocol_paused(ctx: Context<UpdateProtocol>, paused: bool) -> Result<()> {
+    ctx.accounts.protocol.check_admin(ctx.accounts.admin.key)?;
+
+    ctx.accounts.protocol.paused = paused;
+    emit_cpi!(ProtocolUpdated::new(&ctx.accounts.protocol));
+
+    Ok(())
+}
+
+/// First step of an admin handover, mirroring `propose_authority` for pools.
+pub fn propose_protocol_admin(ctx: Context<UpdateProtocol>, new_admin: Pubkey) -> Result<()> {
+    ctx.accounts.protocol.check_admin(ctx.accounts.admin.key)?;
+    ctx.accounts.protocol.pending_admin = Some(new_admin);
+    emit_cpi!(ProtocolUpdated::new(&ctx.accounts.protocol));
+
+    Ok(())
+}
+
+pub fn accept_protocol_admin(ctx: Context<AcceptProtocolAdmin>) -> Result<()> {
+    let protocol = &mut ctx.accounts.protocol;
+    let pending_admin = protocol.pending_admin.ok_or(AmmError::NoAuthoritySet)?;
+    require_keys_eq!(pending_admin, ctx.accounts.pending_admin.key(), AmmError::InvalidAuthority);
+
+    protocol.admin = pending_admin;
+    protocol.pending_admin = None;
+    emit_cpi!(ProtocolUpdated::new(&ctx.accounts.protocol));
+
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use crate::curve::{fee_amount, protocol_fee_amount};
+use crate::errors::AmmError;
+use crate::events;
+use crate::state::{Config, Oracle, ProtocolConfig};
+use crate::utils::{net_amount, transfer_tokens};
+
+/// Accounts each hop of a route takes from `remaining_accounts`, in the order of `Hop`,
+/// before any transfer-hook accounts of its mints.
+const HOP_ACCOUNTS: usize = 10;
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+}
+
+/// One pool of a route. Token accounts must be the user's and the pool's associated token
+/// accounts, and must already exist.
+struct Hop<'info> {
+    config: Account<'info, Config>,
+    mint_in: InterfaceAccount<'info, Mint>,
+    mint_out: InterfaceAccount<'info, Mint>,
+    vault_in: InterfaceAccount<'info, TokenAccount>,
+    vault_out: InterfaceAccount<'info, TokenAccount>,
+    user_in: &'info AccountInfo<'info>,
+    user_out: &'info AccountInfo<'info>,
+    token_program_in: &'info AccountInfo<'info>,
+    token_program_out: &'info AccountInfo<'info>,
+    oracle: Option<Account<'info, Oracle>>,
+}
+
+impl<'info> Hop<'info> {
+    /// Deserializes and checks one hop's accounts.
+    fn load(accounts: &'info [AccountInfo<'info>], user: &Pubkey) -> Result<Self> {
+        let [config, mint_in, mint_out, vault_in, vault_out, user_in, user_out, token_program_in, token_program_out, oracle] = accounts else {
+            return err!(AmmError::InvalidRoute);
+        };
+
+        let config = Account::<Config>::try_from(config)?;
+        let pair = (mint_in.key(), mint_out.key());
+        require!(
+            pair == (config.mint_m, config.mint_n) || pair == (config.mint_n, config.mint_m),
+            AmmError::InvalidMint
+        );
+
+        // Mints are owned by their token program; every token account is the canonical ATA
+        let ata = |wallet: &Pubkey, mint: &AccountInfo, program: &AccountInfo| {
+            get_associated_token_address_with_program_id(wallet, mint.key, program.key)
+        };
+        require_keys_eq!(*mint_in.owner, token_program_in.key(), AmmError::InvalidRoute);
+        require_keys_eq!(*mint_out.owner, token_program_out.key(), AmmError::InvalidRoute);
+        require_keys_eq!(vault_in.key(), ata(&config.key(), mint_in, token_program_in), AmmError::InvalidRoute);
+        require_keys_eq!(vault_out.key(), ata(&config.key(), mint_out, token_program_out), AmmError::InvalidRoute);
+        require_keys_eq!(user_in.key(), ata(user, mint_in, token_program_in), AmmError::InvalidRoute);
+        require_keys_eq!(user_out.key(), ata(user, mint_out, token_program_out), AmmError::InvalidRoute);
+
+        // The program id stands in for a pool without an observation buffer, as for optional accounts
+        let oracle = match oracle.key() == crate::ID {
+            true => None,
+            false => {
+                let oracle = Account::<Oracle>::try_from(oracle)?;
+                let address = Pubkey::create_program_address(
+                    &[b"oracle", config.key().as_ref(), &[oracle.bump]],
+                    &crate::ID,
+                )
+                .map_err(|_| AmmError::InvalidRoute)?;
+                require_keys_eq!(oracle.key(), address, AmmError::InvalidRoute);
+                Some(oracle)
+            }
+        };
+
+        Ok(Self {
+            config,
+            mint_in: InterfaceAccount::try_from(mint_in)?,
+            mint_out: InterfaceAccount::try_from(mint_out)?,
+            vault_in: InterfaceAccount::try_from(vault_in)?,
+            vault_out: InterfaceAccount::try_from(vault_out)?,
+            user_in,
+            user_out,
+            token_program_in,
+            token_program_out,
+            oracle,
+        })
+    }
+
+    /// Swaps `amount_in` from the user's input account through this pool, with the same
+    /// pricing and bookkeeping as `swap`. `hook_accounts` are forwarded to both transfers.
+    /// Returns what reaches the user's output account, and the hop's swap event.
+    fn swap(
+        &mut self,
+        user: &AccountInfo<'info>,
+        amount_in: u64,
+        now: i64,
+        hook_accounts: &[AccountInfo<'info>],
+    ) -> Result<(u64, events::Swap)> {
+        require!(!self.config.locked, AmmError::PoolLocked);
+
+        let is_m = self.mint_in.key() == self.config.mint_m;
+        let (vault_m, vault_n) = match is_m {
+            true => (self.vault_in.amount, self.vault_out.amount),
+            false => (self.vault_out.amount, self.vault_in.amount),
+        };
+        let (reserve_m, reserve_n) = self.config.reserves(vault_m, vault_n)?;
+        let (reserve_in, reserve_out) = match is_m {
+            true => (reserve_m, reserve_n),
+            false => (reserve_n, reserve_m),
+        };
+
+        let amount_in_net = net_amount(&self.mint_in.to_account_info(), amount_in)?;
+        let amount_out = self.config.quote_amount_out(is_m, reserve_in, reserve_out, amount_in_net, now)?;
+        let amount_received = net_amount(&self.mint_out.to_account_info(), amount_out)?;
+        require!(amount_received > 0, AmmError::InvalidAmount);
+
+        let fee = fee_amount(amount_in_net, self.config.effective_fee(now))?;
+        let protocol_fee = protocol_fee_amount(fee, self.config.protocol_fee);
+        self.config.check_oracle(self.oracle.as_ref())?;
+        self.config.update_oracle(reserve_m, reserve_n, now)?;
+        if let Some(oracle) = self.oracle.as_mut() {
+            let info = oracle.to_account_info();
+            oracle.record(&info, &self.config)?;
+            oracle.exit(&crate::ID)?;
+        }
+        self.config.accrue_protocol_fee(is_m, protocol_fee)?;
+        self.config.update_volatility(is_m, (reserve_in, reserve_out), amount_in_net, amount_out, now)?;
+        self.config.exit(&crate::ID)?;
+
+        transfer_tokens(
+            self.token_program_in.clone(),
+            self.mint_in.to_account_info(),
+            self.user_in.clone(),
+            self.vault_in.to_account_info(),
+            user.clone(),
+            amount_in,
+            self.mint_in.decimals,
+            &[],
+            hook_accounts,
+        )?;
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+        transfer_tokens(
+            self.token_program_out.clone(),
+            self.mint_out.to_account_info(),
+            self.vault_out.to_account_info(),
+            self.user_out.clone(),
+            self.config.to_account_info(),
+            amount_out,
+            self.mint_out.decimals,
+            &[&seeds],
+            hook_accounts,
+        )?;
+
+        self.vault_in.reload()?;
+        self.vault_out.reload()?;
+        let (reserve_m_after, reserve_n_after) = match is_m {
+            true => self.config.reserves(self.vault_in.amount, self.vault_out.amount)?,
+            false => self.config.reserves(self.vault_out.amount, self.vault_in.amount)?,
+        };
+        let event = events::Swap {
+            config: self.config.key(),
+            user: user.key(),
+            is_m,
+            amount_in: amount_in_net,
+            amount_out,
+            fee,
+            protocol_fee,
+            reserve_m_before: reserve_m,
+            reserve_n_before: reserve_n,
+            reserve_m_after,
+            reserve_n_after,
+        };
+
+        Ok((amount_received, event))
+    }
+}
+
+/// Swaps `amount_in` along a route of pools, each hop paying the previous hop's output into
+/// the next. Every hop takes HOP_ACCOUNTS accounts from `remaining_accounts`:
+/// config, mint_in, mint_out, vault_in, vault_out, user_in, user_out, token_program_in,
+/// token_program_out, oracle (the program id for none), followed by `hook_accounts[i]` extra accounts for the transfer hooks
+/// of its mints. Slippage is only checked on the final output, so the route executes
+/// in full or not at all.
+pub fn route_swap<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+    amount_in: u64,
+    min_out: u64,
+    expiration: i64,
+    hook_accounts: Vec<u8>,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount_in > 0, AmmError::InvalidAmount);
+    require!(!hook_accounts.is_empty(), AmmError::InvalidRoute);
+
+    let user = ctx.accounts.user.to_account_info();
+    let mut amount = amount_in;
+    let mut previous_mint_out = None;
+    let mut remaining = ctx.remaining_accounts;
+    for hooks in hook_accounts {
+        let hop_len = HOP_ACCOUNTS + hooks as usize;
+        require!(remaining.len() >= hop_len, AmmError::InvalidRoute);
+        let (accounts, rest) = remaining.split_at(hop_len);
+        let (accounts, hook_accounts) = accounts.split_at(HOP_ACCOUNTS);
+        remaining = rest;
+
+        let mut hop = Hop::load(accounts, user.key)?;
+        if let Some(mint) = previous_mint_out {
+            require_keys_eq!(hop.mint_in.key(), mint, AmmError::InvalidRoute);
+        }
+        previous_mint_out = Some(hop.mint_out.key());
+
+        let (received, event) = hop.swap(&user, amount, now, hook_accounts)?;
+        emit_cpi!(event);
+        amount = received;
+    }
+    require!(remaining.is_empty(), AmmError::InvalidRoute);
+
+    require!(amount >= min_out, AmmError::SlippageExceeded);
+
+    Ok(())
+}
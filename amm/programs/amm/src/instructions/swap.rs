@@ -0,0 +1,273 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::curve::{fee_amount, protocol_fee_amount};
+use crate::errors::AmmError;
+use crate::events;
+use crate::state::{Config, Oracle, ProtocolConfig};
+use crate::utils::{gross_amount, net_amount, transfer_tokens};
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_m,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_m,
+    )]
+    pub user_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_n,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_n,
+    )]
+    pub user_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(
+        mut,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Option<Box<Account<'info, Oracle>>>, // Records an observation; required once the pool's oracle is initialized
+}
+
+impl<'info> Swap<'info> {
+    /// Returns the (input, output) reserves for a swap paying in token M when `is_m` is set.
+    pub fn reserves(&self, is_m: bool) -> Result<(u64, u64)> {
+        let (reserve_m, reserve_n) = self.config.reserves(self.vault_m.amount, self.vault_n.amount)?;
+        match is_m {
+            true => Ok((reserve_m, reserve_n)),
+            false => Ok((reserve_n, reserve_m)),
+        }
+    }
+
+    /// Returns the mint of token M when `is_m` is set, token N otherwise.
+    pub fn mint(&self, is_m: bool) -> AccountInfo<'info> {
+        match is_m {
+            true => self.mint_m.to_account_info(),
+            false => self.mint_n.to_account_info(),
+        }
+    }
+
+    /// Advances the pool's TWAP accumulators using the reserves from before this swap,
+    /// and records the result in the observation buffer, which is required once initialized.
+    pub fn update_oracle(&mut self, now: i64) -> Result<()> {
+        self.config.check_oracle(self.oracle.as_ref())?;
+        let (reserve_m, reserve_n) = self.config.reserves(self.vault_m.amount, self.vault_n.amount)?;
+        self.config.update_oracle(reserve_m, reserve_n, now)?;
+
+        if let Some(oracle) = self.oracle.as_mut() {
+            let info = oracle.to_account_info();
+            oracle.record(&info, &self.config)?;
+        }
+        Ok(())
+    }
+
+    /// Sets aside the treasury's share of the fee paid on the input side.
+    /// `amount_in` is what actually reached the vault. Returns the fee and the treasury's share.
+    pub fn accrue_protocol_fee(&mut self, is_m: bool, amount_in: u64, now: i64) -> Result<(u64, u64)> {
+        let fee = fee_amount(amount_in, self.config.effective_fee(now))?;
+        let protocol_fee = protocol_fee_amount(fee, self.config.protocol_fee);
+        self.config.accrue_protocol_fee(is_m, protocol_fee)?;
+        Ok((fee, protocol_fee))
+    }
+
+    /// Reloads the vaults once both legs have settled and describes the swap,
+    /// given the (input, output) reserves it was priced on.
+    pub fn swap_event(
+        &mut self,
+        is_m: bool,
+        (amount_in, amount_out): (u64, u64),
+        (fee, protocol_fee): (u64, u64),
+        (reserve_in, reserve_out): (u64, u64),
+    ) -> Result<events::Swap> {
+        self.vault_m.reload()?;
+        self.vault_n.reload()?;
+        let (reserve_m_after, reserve_n_after) = self.config.reserves(self.vault_m.amount, self.vault_n.amount)?;
+        let (reserve_m_before, reserve_n_before) = match is_m {
+            true => (reserve_in, reserve_out),
+            false => (reserve_out, reserve_in),
+        };
+
+        Ok(events::Swap {
+            config: self.config.key(),
+            user: self.user.key(),
+            is_m,
+            amount_in,
+            amount_out,
+            fee,
+            protocol_fee,
+            reserve_m_before,
+            reserve_n_before,
+            reserve_m_after,
+            reserve_n_after,
+        })
+    }
+
+    /// Transfers the input side from the user into the matching vault.
+    pub fn deposit_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        transfer_tokens(program, mint, from, to, self.user.to_account_info(), amount, decimals, &[], remaining_accounts)
+    }
+
+    /// Transfers the output side from its vault to the user, signed by the config PDA.
+    pub fn withdraw_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+
+        transfer_tokens(program, mint, from, to, self.config.to_account_info(), amount, decimals, &[&seeds], remaining_accounts)
+    }
+}
+
+pub fn swap<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+    is_m: bool,
+    amount_in: u64,
+    min_out: u64,
+    expiration: i64,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount_in > 0, AmmError::InvalidAmount);
+
+    // Price the trade on what actually reaches the vault, under the pool's curve, after deducting the pool fee
+    let (reserve_in, reserve_out) = ctx.accounts.reserves(is_m)?;
+    let amount_in_net = net_amount(&ctx.accounts.mint(is_m), amount_in)?;
+    let amount_out = ctx.accounts.config.quote_amount_out(is_m, reserve_in, reserve_out, amount_in_net, now)?;
+
+    // Slippage is checked against what the user receives after any transfer fee on the output mint
+    let amount_received = net_amount(&ctx.accounts.mint(!is_m), amount_out)?;
+    require!(amount_received > 0, AmmError::InvalidAmount);
+    require!(amount_received >= min_out, AmmError::SlippageExceeded);
+
+    // Pull the input side into its vault, then pay out the other side
+    ctx.accounts.update_oracle(now)?;
+    let fees = ctx.accounts.accrue_protocol_fee(is_m, amount_in_net, now)?;
+    ctx.accounts.config.update_volatility(is_m, (reserve_in, reserve_out), amount_in_net, amount_out, now)?;
+    ctx.accounts.deposit_tokens(is_m, amount_in, ctx.remaining_accounts)?;
+    ctx.accounts.withdraw_tokens(!is_m, amount_out, ctx.remaining_accounts)?;
+
+    let event = ctx.accounts.swap_event(is_m, (amount_in_net, amount_out), fees, (reserve_in, reserve_out))?;
+    emit_cpi!(event);
+
+    Ok(())
+}
+
+pub fn swap_exact_out<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+    is_m: bool,
+    amount_out: u64,
+    max_in: u64,
+    expiration: i64,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount_out > 0, AmmError::InvalidAmount);
+
+    // Work backwards from the requested output to the input the pool needs, fee included.
+    // `amount_out` is what the user receives, so both legs are grossed up for transfer fees.
+    let (reserve_in, reserve_out) = ctx.accounts.reserves(is_m)?;
+    let amount_out_gross = gross_amount(&ctx.accounts.mint(!is_m), amount_out)?;
+    let amount_in_net = ctx.accounts.config.quote_amount_in(is_m, reserve_in, reserve_out, amount_out_gross, now)?;
+    let amount_in = gross_amount(&ctx.accounts.mint(is_m), amount_in_net)?;
+
+    require!(amount_in <= max_in, AmmError::SlippageExceeded);
+
+    ctx.accounts.update_oracle(now)?;
+    let fees = ctx.accounts.accrue_protocol_fee(is_m, amount_in_net, now)?;
+    ctx.accounts.config.update_volatility(is_m, (reserve_in, reserve_out), amount_in_net, amount_out_gross, now)?;
+    ctx.accounts.deposit_tokens(is_m, amount_in, ctx.remaining_accounts)?;
+    ctx.accounts.withdraw_tokens(!is_m, amount_out_gross, ctx.remaining_accounts)?;
+
+    let event = ctx.accounts.swap_event(is_m, (amount_in_net, amount_out_gross), fees, (reserve_in, reserve_out))?;
+    emit_cpi!(event);
+
+    Ok(())
+}
@@ -0,0 +1,325 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::clmm::{compute_swap_step, mul_div, sqrt_price_at_tick, tick_at_sqrt_price, MAX_TICK, MIN_TICK, Q64};
+use crate::curve::protocol_fee_amount;
+use crate::errors::AmmError;
+use crate::events;
+use crate::state::{Config, CurveType, Oracle, ProtocolConfig, TickArray};
+use crate::utils::{net_amount, transfer_tokens};
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapConcentrated<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_m,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_m,
+    )]
+    pub user_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_n,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_n,
+    )]
+    pub user_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// Array holding the current tick
+    #[account(mut, has_one = config @ AmmError::InvalidTickArray)]
+    pub tick_array_0: Box<Account<'info, TickArray>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    /// Next arrays in the direction of the swap, for trades that move the price further
+    #[account(mut, has_one = config @ AmmError::InvalidTickArray)]
+    pub tick_array_1: Option<Box<Account<'info, TickArray>>>,
+    #[account(mut, has_one = config @ AmmError::InvalidTickArray)]
+    pub tick_array_2: Option<Box<Account<'info, TickArray>>>,
+    #[account(
+        mut,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Option<Box<Account<'info, Oracle>>>, // Records an observation; required once the pool's oracle is initialized
+}
+
+impl<'info> SwapConcentrated<'info> {
+    /// Returns the mint of token M when `is_m` is set, token N otherwise.
+    pub fn mint(&self, is_m: bool) -> AccountInfo<'info> {
+        match is_m {
+            true => self.mint_m.to_account_info(),
+            false => self.mint_n.to_account_info(),
+        }
+    }
+
+    /// Transfers the input side from the user into the matching vault.
+    pub fn deposit_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        transfer_tokens(program, mint, from, to, self.user.to_account_info(), amount, decimals, &[], remaining_accounts)
+    }
+
+    /// Transfers the output side from its vault to the user, signed by the config PDA.
+    pub fn withdraw_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+
+        transfer_tokens(program, mint, from, to, self.config.to_account_info(), amount, decimals, &[&seeds], remaining_accounts)
+    }
+}
+
+/// Next tick the price can move to in the direction of the swap: the nearest initialized tick
+/// within the provided arrays, or failing that the last tick they cover (or the price bound).
+/// Returns None once the price has run past the provided arrays.
+fn next_tick(tick_arrays: &[&mut TickArray], tick_current: i32, tick_spacing: u16, is_m: bool) -> Option<(i32, bool)> {
+    let spacing = tick_spacing as i32;
+    let step = match is_m {
+        true => -spacing,
+        false => spacing,
+    };
+
+    // Selling M moves the price down onto the current tick first; selling N moves up past it
+    let mut tick = tick_current.div_euclid(spacing) * spacing;
+    if !is_m {
+        tick += step;
+    }
+
+    while (MIN_TICK..=MAX_TICK).contains(&tick) {
+        let array = tick_arrays.iter().find(|a| a.contains(tick, tick_spacing))?;
+        let offset = ((tick - array.start_tick_index) / spacing) as usize;
+        if array.ticks[offset].liquidity_gross > 0 {
+            return Some((tick, true));
+        }
+
+        // Stop at the edge of the provided arrays
+        if !tick_arrays.iter().any(|a| a.contains(tick + step, tick_spacing)) {
+            return Some((tick, false));
+        }
+        tick += step;
+    }
+
+    Some((tick.clamp(MIN_TICK, MAX_TICK), false))
+}
+
+/// Moves the pool's price through the provided tick arrays until `amount_in` is used up,
+/// crossing initialized ticks on the way. Fees, at the rate `fee`, are split between the treasury and the
+/// in-range liquidity. Returns the output amount, the fees charged and the treasury's share of them.
+fn swap_through_ticks(config: &mut Config, tick_arrays: &mut [&mut TickArray], is_m: bool, amount_in: u64, fee: u16) -> Result<(u64, u64, u64)> {
+    let spacing = config.tick_spacing;
+    let span = TickArray::span(spacing);
+
+    // The arrays must start at the current tick and follow on from each other in the swap direction
+    require!(tick_arrays[0].contains(config.tick_current, spacing), AmmError::InvalidTickArray);
+    for i in 1..tick_arrays.len() {
+        let expected = match is_m {
+            true => tick_arrays[i - 1].start_tick_index - span,
+            false => tick_arrays[i - 1].start_tick_index + span,
+        };
+        require!(tick_arrays[i].start_tick_index == expected, AmmError::InvalidTickArray);
+    }
+
+    let mut remaining = amount_in;
+    let mut amount_out: u64 = 0;
+    let (mut fees, mut protocol_fees): (u64, u64) = (0, 0);
+    while remaining > 0 {
+        let (target, initialized) = next_tick(tick_arrays, config.tick_current, spacing, is_m)
+            .ok_or(AmmError::TickArraysExhausted)?;
+        let sqrt_price_target = sqrt_price_at_tick(target)?;
+        require!(
+            initialized || sqrt_price_target != config.sqrt_price_x64,
+            AmmError::TickArraysExhausted
+        );
+
+        let step = compute_swap_step(is_m, config.sqrt_price_x64, sqrt_price_target, config.liquidity, remaining, fee)?;
+        remaining -= step.amount_in + step.fee_amount;
+        amount_out = amount_out.checked_add(step.amount_out).ok_or(AmmError::Overflow)?;
+
+        // The treasury takes its cut first; the rest is shared by the liquidity in range
+        let protocol_fee = protocol_fee_amount(step.fee_amount, config.protocol_fee);
+        config.accrue_protocol_fee(is_m, protocol_fee)?;
+        fees = fees.checked_add(step.fee_amount).ok_or(AmmError::Overflow)?;
+        protocol_fees = protocol_fees.checked_add(protocol_fee).ok_or(AmmError::Overflow)?;
+        if config.liquidity > 0 {
+            let growth = mul_div((step.fee_amount - protocol_fee) as u128, Q64, config.liquidity, false)?;
+            match is_m {
+                true => config.fee_growth_global_m_x64 = config.fee_growth_global_m_x64.wrapping_add(growth),
+                false => config.fee_growth_global_n_x64 = config.fee_growth_global_n_x64.wrapping_add(growth),
+            }
+        }
+
+        if step.sqrt_price_next == sqrt_price_target {
+            if initialized {
+                let array = tick_arrays
+                    .iter_mut()
+                    .find(|a| a.contains(target, spacing))
+                    .ok_or(AmmError::InvalidTickArray)?;
+                let net = array
+                    .tick_mut(target, spacing)?
+                    .cross(config.fee_growth_global_m_x64, config.fee_growth_global_n_x64, is_m);
+                config.liquidity = config.liquidity
+                    .checked_add_signed(net)
+                    .ok_or(AmmError::Overflow)?;
+            }
+            config.tick_current = match is_m {
+                true => target - 1,
+                false => target,
+            };
+        } else {
+            config.tick_current = tick_at_sqrt_price(step.sqrt_price_next)?;
+        }
+        config.sqrt_price_x64 = step.sqrt_price_next;
+    }
+
+    Ok((amount_out, fees, protocol_fees))
+}
+
+/// Swaps an exact amount of token M (`is_m`) or token N against a concentrated pool.
+/// The whole input must fit within the liquidity covered by the provided tick arrays.
+pub fn swap_concentrated<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SwapConcentrated<'info>>,
+    is_m: bool,
+    amount_in: u64,
+    min_out: u64,
+    expiration: i64,
+) -> Result<()> {
+    require!(ctx.accounts.config.curve == CurveType::Concentrated, AmmError::InvalidCurve);
+    ctx.accounts.protocol.check_not_paused()?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount_in > 0, AmmError::InvalidAmount);
+
+    // Accumulate the TWAP at the price held up to now, before the swap moves it
+    let accounts = &mut *ctx.accounts;
+    let (reserve_m_before, reserve_n_before) = accounts.config.reserves(accounts.vault_m.amount, accounts.vault_n.amount)?;
+    accounts.config.check_oracle(accounts.oracle.as_ref())?;
+    accounts.config.update_oracle(reserve_m_before, reserve_n_before, now)?;
+    if let Some(oracle) = accounts.oracle.as_mut() {
+        let info = oracle.to_account_info();
+        oracle.record(&info, &accounts.config)?;
+    }
+
+    // Price the trade on what actually reaches the vault
+    let amount_in_net = net_amount(&accounts.mint(is_m), amount_in)?;
+    let mut tick_arrays: Vec<&mut TickArray> = vec![&mut **accounts.tick_array_0];
+    if let Some(tick_array) = accounts.tick_array_1.as_mut() {
+        tick_arrays.push(&mut ***tick_array);
+    }
+    if let Some(tick_array) = accounts.tick_array_2.as_mut() {
+        tick_arrays.push(&mut ***tick_array);
+    }
+    let fee = accounts.config.effective_fee(now);
+    let (price_before, _) = accounts.config.spot_prices(accounts.vault_m.amount, accounts.vault_n.amount, now)?;
+    let (amount_out, fees, protocol_fees) = swap_through_ticks(&mut accounts.config, &mut tick_arrays, is_m, amount_in_net, fee)?;
+    if accounts.config.dynamic_fee {
+        let (price_after, _) = accounts.config.spot_prices(accounts.vault_m.amount, accounts.vault_n.amount, now)?;
+        accounts.config.record_price_move(price_before, price_after, now)?;
+    }
+
+    // Slippage is checked against what the user receives after any transfer fee on the output mint
+    let amount_received = net_amount(&accounts.mint(!is_m), amount_out)?;
+    require!(amount_received > 0, AmmError::InvalidAmount);
+    require!(amount_received >= min_out, AmmError::SlippageExceeded);
+
+    accounts.deposit_tokens(is_m, amount_in, ctx.remaining_accounts)?;
+    accounts.withdraw_tokens(!is_m, amount_out, ctx.remaining_accounts)?;
+
+    accounts.vault_m.reload()?;
+    accounts.vault_n.reload()?;
+    let (reserve_m_after, reserve_n_after) = accounts.config.reserves(accounts.vault_m.amount, accounts.vault_n.amount)?;
+    let event = events::Swap {
+        config: accounts.config.key(),
+        user: accounts.user.key(),
+        is_m,
+        amount_in: amount_in_net,
+        amount_out,
+        fee: fees,
+        protocol_fee: protocol_fees,
+        reserve_m_before,
+        reserve_n_before,
+        reserve_m_after,
+        reserve_n_after,
+    };
+    emit_cpi!(event);
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use anchor_lang::prelude::*;
+use crate::errors::AmmError;
+use crate::events::{AuthorityUpdated, PoolLocked};
+use crate::state::{Config, ProtocolConfig};
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Update<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Account<'info, ProtocolConfig>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub pending_authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), config.mint_m.as_ref(), config.mint_n.as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+pub fn lock(ctx: Context<Update>) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    ctx.accounts.config.locked = true;
+    emit_cpi!(PoolLocked {
+        config: ctx.accounts.config.key(),
+        authority: ctx.accounts.authority.key(),
+        locked: true,
+    });
+
+    Ok(())
+}
+
+pub fn unlock(ctx: Context<Update>) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    ctx.accounts.config.locked = false;
+    emit_cpi!(PoolLocked {
+        config: ctx.accounts.config.key(),
+        authority: ctx.accounts.authority.key(),
+        locked: false,
+    });
+
+    Ok(())
+}
+
+/// First step of an authority handover: the new authority only takes over once it accepts.
+/// Proposing again replaces the pending authority.
+pub fn propose_authority(ctx: Context<Update>, new_authority: Pubkey) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    ctx.accounts.config.pending_authority = Some(new_authority);
+    emit_cpi!(AuthorityUpdated::new(&ctx.accounts.config));
+
+    Ok(())
+}
+
+pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    require!(config.authority.is_some(), AmmError::AmmIsImmutable);
+
+    let pending_authority = config.pending_authority.ok_or(AmmError::NoAuthoritySet)?;
+    require_keys_eq!(pending_authority, ctx.accounts.pending_authority.key(), AmmError::InvalidAuthority);
+
+    config.authority = Some(pending_authority);
+    config.pending_authority = None;
+    emit_cpi!(AuthorityUpdated::new(&ctx.accounts.config));
+
+    Ok(())
+}
+
+/// Permanently gives up control of the pool. Its fee and lock state can never change afterwards,
+/// so a locked pool cannot be renounced and any queued fee change is dropped. The protocol fee
+/// share stays with the protocol admin, so a change it queued survives.
+pub fn renounce_authority(ctx: Context<Update>) -> Result<()> {
+    ctx.accounts.config.check_authority(ctx.accounts.authority.key)?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+
+    ctx.accounts.config.authority = None;
+    ctx.accounts.config.pending_authority = None;
+    ctx.accounts.config.pending_fee = None;
+    ctx.accounts.config.pending_dynamic_fee = None;
+    emit_cpi!(AuthorityUpdated::new(&ctx.accounts.config));
+
+    Ok(())
+}
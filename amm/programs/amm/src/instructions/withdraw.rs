@@ -0,0 +1,175 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, burn, Burn};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::curve::quote_withdraw;
+use crate::errors::AmmError;
+use crate::events::LiquidityRemoved;
+use crate::state::{Config, Oracle};
+use crate::utils::{net_amount, transfer_tokens};
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"liquiditypool", config.key().as_ref()],
+        bump = config.bump_lp,
+        mint::token_program = token_program_lp,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_m,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_m,
+    )]
+    pub user_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_n,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_n,
+    )]
+    pub user_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_lp,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub token_program_lp: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(
+        mut,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Option<Box<Account<'info, Oracle>>>, // Records an observation; required once the pool's oracle is initialized
+}
+
+impl<'info> Withdraw<'info> {
+    pub fn withdraw_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+
+        transfer_tokens(program, mint, from, to, self.config.to_account_info(), amount, decimals, &[&seeds], remaining_accounts)
+    }
+
+    pub fn burn_lp_tokens(&self, amount: u64) -> Result<()> {
+        let cpi_accounts = Burn {
+            mint: self.mint_lp.to_account_info(),
+            from: self.user_lp.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+
+        burn(CpiContext::new(self.token_program_lp.to_account_info(), cpi_accounts), amount)
+    }
+}
+
+/// Burns `amount` LP for its pro-rata share of the reserves. Locked pools still allow this,
+/// so a lock never traps LPs' funds.
+pub fn withdraw<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Withdraw<'info>>,
+    amount: u64,
+    min_m: u64,
+    min_n: u64,
+    expiration: i64,
+) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount > 0, AmmError::InvalidAmount);
+    require!(amount <= ctx.accounts.user_lp.amount, AmmError::InsufficientBalance);
+
+    // Pay out the holder's pro-rata share of each vault: reserve * amount / total_lp, rounded down
+    let (reserve_m, reserve_n) = ctx.accounts.config.reserves(ctx.accounts.vault_m.amount, ctx.accounts.vault_n.amount)?;
+    let (m, n) = quote_withdraw(reserve_m, reserve_n, ctx.accounts.mint_lp.supply, amount)?;
+
+    // Slippage is checked against what the holder receives after any transfer fee
+    let m_received = net_amount(&ctx.accounts.mint_m.to_account_info(), m)?;
+    let n_received = net_amount(&ctx.accounts.mint_n.to_account_info(), n)?;
+    require!(m_received >= min_m && n_received >= min_n, AmmError::SlippageExceeded);
+
+    ctx.accounts.config.check_oracle(ctx.accounts.oracle.as_ref())?;
+    ctx.accounts.config.update_oracle(reserve_m, reserve_n, now)?;
+    if let Some(oracle) = ctx.accounts.oracle.as_mut() {
+        let info = oracle.to_account_info();
+        oracle.record(&info, &ctx.accounts.config)?;
+    }
+    ctx.accounts.burn_lp_tokens(amount)?;
+    ctx.accounts.withdraw_tokens(true, m, ctx.remaining_accounts)?;
+    ctx.accounts.withdraw_tokens(false, n, ctx.remaining_accounts)?;
+
+    ctx.accounts.vault_m.reload()?;
+    ctx.accounts.vault_n.reload()?;
+    let (reserve_m_after, reserve_n_after) = ctx.accounts.config.reserves(ctx.accounts.vault_m.amount, ctx.accounts.vault_n.amount)?;
+    emit_cpi!(LiquidityRemoved {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.user.key(),
+        position: None,
+        amount_m: m,
+        amount_n: n,
+        liquidity: amount as u128,
+        reserve_m_before: reserve_m,
+        reserve_n_before: reserve_n,
+        reserve_m_after,
+        reserve_n_after,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,391 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface, burn, mint_to, Burn, MintTo};
+use anchor_spl::associated_token::AssociatedToken;
+use crate::curve::{protocol_fee_amount, quote_zap_in, quote_zap_out};
+use crate::errors::AmmError;
+use crate::events;
+use crate::state::{Config, Oracle, ProtocolConfig};
+use crate::utils::{gross_amount, net_amount, transfer_tokens};
+
+/// Accounts for providing or removing liquidity with a single token, swapping the other side
+/// through the same pool.
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Zap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mint::token_program = token_program_m)]
+    pub mint_m: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = token_program_n)]
+    pub mint_n: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = mint_m @ AmmError::InvalidMint,
+        has_one = mint_n @ AmmError::InvalidMint,
+        seeds = [b"config", config.seed.to_le_bytes().as_ref(), mint_m.key().as_ref(), mint_n.key().as_ref()],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"protocol"],
+        bump = protocol.bump,
+    )]
+    pub protocol: Box<Account<'info, ProtocolConfig>>,
+    #[account(
+        mut,
+        seeds = [b"liquiditypool", config.key().as_ref()],
+        bump = config.bump_lp,
+        mint::token_program = token_program_lp,
+    )]
+    pub mint_lp: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_m,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_m,
+    )]
+    pub vault_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_n,
+        associated_token::authority = config,
+        associated_token::token_program = token_program_n,
+    )]
+    pub vault_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_m,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_m,
+    )]
+    pub user_m: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_n,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_n,
+    )]
+    pub user_n: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = mint_lp,
+        associated_token::authority = user,
+        associated_token::token_program = token_program_lp,
+    )]
+    pub user_lp: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub system_program: Program<'info, System>,
+    pub token_program_m: Interface<'info, TokenInterface>,
+    pub token_program_n: Interface<'info, TokenInterface>,
+    pub token_program_lp: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account(
+        mut,
+        seeds = [b"oracle", config.key().as_ref()],
+        bump = oracle.bump,
+    )]
+    pub oracle: Option<Box<Account<'info, Oracle>>>, // Records an observation; required once the pool's oracle is initialized
+}
+
+impl<'info> Zap<'info> {
+    /// Vault balances less accrued protocol fees, as (M, N).
+    pub fn reserves(&self) -> Result<(u64, u64)> {
+        self.config.reserves(self.vault_m.amount, self.vault_n.amount)
+    }
+
+    /// Returns the mint of token M when `is_m` is set, token N otherwise.
+    pub fn mint(&self, is_m: bool) -> AccountInfo<'info> {
+        match is_m {
+            true => self.mint_m.to_account_info(),
+            false => self.mint_n.to_account_info(),
+        }
+    }
+
+    /// Advances the pool's TWAP accumulators using the reserves from before the zap,
+    /// and records the result in the observation buffer, which is required once initialized.
+    pub fn update_oracle(&mut self, reserve_m: u64, reserve_n: u64, now: i64) -> Result<()> {
+        self.config.check_oracle(self.oracle.as_ref())?;
+        self.config.update_oracle(reserve_m, reserve_n, now)?;
+
+        if let Some(oracle) = self.oracle.as_mut() {
+            let info = oracle.to_account_info();
+            oracle.record(&info, &self.config)?;
+        }
+        Ok(())
+    }
+
+    /// Transfers token M (`is_m`) or token N from the user into the matching vault.
+    pub fn deposit_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        transfer_tokens(program, mint, from, to, self.user.to_account_info(), amount, decimals, &[], remaining_accounts)
+    }
+
+    /// Transfers token M (`is_m`) or token N from its vault to the user, signed by the config PDA.
+    pub fn withdraw_tokens(
+        &self,
+        is_m: bool,
+        amount: u64,
+        remaining_accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let (program, mint, from, to, decimals) = match is_m {
+            true => (
+                self.token_program_m.to_account_info(),
+                self.mint_m.to_account_info(),
+                self.vault_m.to_account_info(),
+                self.user_m.to_account_info(),
+                self.mint_m.decimals,
+            ),
+            false => (
+                self.token_program_n.to_account_info(),
+                self.mint_n.to_account_info(),
+                self.vault_n.to_account_info(),
+                self.user_n.to_account_info(),
+                self.mint_n.decimals,
+            ),
+        };
+
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+
+        transfer_tokens(program, mint, from, to, self.config.to_account_info(), amount, decimals, &[&seeds], remaining_accounts)
+    }
+
+    pub fn mint_lp_tokens(&self, amount: u64) -> Result<()> {
+        let seed_bytes = self.config.seed.to_le_bytes();
+        let seeds = self.config.signer_seeds(&seed_bytes);
+        let signer_seeds = &[&seeds[..]];
+
+        let cpi_accounts = MintTo {
+            mint: self.mint_lp.to_account_info(),
+            to: self.user_lp.to_account_info(),
+            authority: self.config.to_account_info(),
+        };
+
+        mint_to(
+            CpiContext::new_with_signer(
+                self.token_program_lp.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            ),
+            amount,
+        )
+    }
+
+    pub fn burn_lp_tokens(&self, amount: u64) -> Result<()> {
+        let cpi_accounts = Burn {
+            mint: self.mint_lp.to_account_info(),
+            from: self.user_lp.to_account_info(),
+            authority: self.user.to_account_info(),
+        };
+
+        burn(CpiContext::new(self.token_program_lp.to_account_info(), cpi_accounts), amount)
+    }
+}
+
+/// Provides liquidity with `amount_in` of token M (`is_m`) or token N only. Part of it is sold
+/// to the pool for the other token, sized so the rest matches the pool ratio after the swap,
+/// and both sides are deposited. The swap proceeds never leave the vault; only the ones the
+/// deposit cannot use are paid back. Input the deposit cannot use stays with the user.
+pub fn zap_in<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Zap<'info>>,
+    is_m: bool,
+    amount_in: u64,
+    min_lp_out: u64,
+    expiration: i64,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount_in > 0, AmmError::InvalidAmount);
+
+    let (reserve_m, reserve_n) = ctx.accounts.reserves()?;
+    require!(reserve_m > 0 && reserve_n > 0, AmmError::PoolNotInitialized);
+    let (reserve_in, reserve_out) = match is_m {
+        true => (reserve_m, reserve_n),
+        false => (reserve_n, reserve_m),
+    };
+
+    // Plan on what actually reaches the vault
+    let amount_in_net = net_amount(&ctx.accounts.mint(is_m), amount_in)?;
+    let config = &ctx.accounts.config;
+    let quote = quote_zap_in(
+        config.pricing_curve(is_m, now)?,
+        reserve_in,
+        reserve_out,
+        ctx.accounts.mint_lp.supply,
+        amount_in_net,
+        config.effective_fee(now),
+        config.protocol_fee,
+    )?;
+    require!(quote.lp_amount > 0, AmmError::InvalidAmount);
+    require!(quote.lp_amount >= min_lp_out, AmmError::SlippageExceeded);
+
+    // Book the swap leg exactly as `swap` would
+    let swap = quote.swap;
+    let protocol_fee = protocol_fee_amount(swap.fee, ctx.accounts.config.protocol_fee);
+    ctx.accounts.update_oracle(reserve_m, reserve_n, now)?;
+    ctx.accounts.config.accrue_protocol_fee(is_m, protocol_fee)?;
+    ctx.accounts.config.update_volatility(is_m, (reserve_in, reserve_out), swap.amount_in, swap.amount_out, now)?;
+
+    // One transfer covers both the swap leg and the deposit on the input side
+    let spent = swap.amount_in.checked_add(quote.deposit_in).ok_or(AmmError::Overflow)?;
+    let spent_gross = gross_amount(&ctx.accounts.mint(is_m), spent)?;
+    ctx.accounts.deposit_tokens(is_m, spent_gross, ctx.remaining_accounts)?;
+    let refund = swap.amount_out.checked_sub(quote.deposit_out).ok_or(AmmError::Underflow)?;
+    if refund > 0 {
+        ctx.accounts.withdraw_tokens(!is_m, refund, ctx.remaining_accounts)?;
+    }
+    ctx.accounts.mint_lp_tokens(quote.lp_amount)?;
+
+    // Reserves between the two legs, as (input, output)
+    let swapped_in = reserve_in
+        .checked_add(swap.amount_in)
+        .and_then(|reserve| reserve.checked_sub(protocol_fee))
+        .ok_or(AmmError::Overflow)?;
+    let swapped_out = reserve_out.checked_sub(swap.amount_out).ok_or(AmmError::Underflow)?;
+    let (swapped_m, swapped_n, amount_m, amount_n) = match is_m {
+        true => (swapped_in, swapped_out, quote.deposit_in, quote.deposit_out),
+        false => (swapped_out, swapped_in, quote.deposit_out, quote.deposit_in),
+    };
+
+    ctx.accounts.vault_m.reload()?;
+    ctx.accounts.vault_n.reload()?;
+    let (reserve_m_after, reserve_n_after) = ctx.accounts.reserves()?;
+    emit_cpi!(events::Swap {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.user.key(),
+        is_m,
+        amount_in: swap.amount_in,
+        amount_out: swap.amount_out,
+        fee: swap.fee,
+        protocol_fee,
+        reserve_m_before: reserve_m,
+        reserve_n_before: reserve_n,
+        reserve_m_after: swapped_m,
+        reserve_n_after: swapped_n,
+    });
+    emit_cpi!(events::LiquidityAdded {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.user.key(),
+        position: None,
+        amount_m,
+        amount_n,
+        liquidity: quote.lp_amount as u128,
+        reserve_m_before: swapped_m,
+        reserve_n_before: swapped_n,
+        reserve_m_after,
+        reserve_n_after,
+    });
+
+    Ok(())
+}
+
+/// Burns `amount` LP and pays out everything in token M (`is_m`) or token N. The other side's
+/// share is sold back to the pool at the reserves left after the withdrawal, so it never
+/// leaves the vault. `min_out` is checked against what the user receives after any transfer fee.
+pub fn zap_out<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Zap<'info>>,
+    is_m: bool,
+    amount: u64,
+    min_out: u64,
+    expiration: i64,
+) -> Result<()> {
+    ctx.accounts.protocol.check_not_paused()?;
+    require!(!ctx.accounts.config.locked, AmmError::PoolLocked);
+    let now = Clock::get()?.unix_timestamp;
+    require!(now <= expiration, AmmError::OfferExpired);
+    require!(amount > 0, AmmError::InvalidAmount);
+    require!(amount <= ctx.accounts.user_lp.amount, AmmError::InsufficientBalance);
+
+    // The side sold back is the swap's input; the chosen token is its output
+    let (reserve_m, reserve_n) = ctx.accounts.reserves()?;
+    let (reserve_in, reserve_out) = match is_m {
+        true => (reserve_n, reserve_m),
+        false => (reserve_m, reserve_n),
+    };
+    let config = &ctx.accounts.config;
+    let quote = quote_zap_out(
+        config.pricing_curve(!is_m, now)?,
+        reserve_in,
+        reserve_out,
+        ctx.accounts.mint_lp.supply,
+        amount,
+        config.effective_fee(now),
+    )?;
+
+    let amount_received = net_amount(&ctx.accounts.mint(is_m), quote.amount_out)?;
+    require!(amount_received > 0, AmmError::InvalidAmount);
+    require!(amount_received >= min_out, AmmError::SlippageExceeded);
+
+    // Book the swap leg exactly as `swap` would, at the reserves left by the withdrawal
+    let swap = quote.swap;
+    let withdrawn_in = reserve_in.checked_sub(quote.withdraw_in).ok_or(AmmError::Underflow)?;
+    let withdrawn_out = reserve_out.checked_sub(quote.withdraw_out).ok_or(AmmError::Underflow)?;
+    let protocol_fee = protocol_fee_amount(swap.fee, ctx.accounts.config.protocol_fee);
+    ctx.accounts.update_oracle(reserve_m, reserve_n, now)?;
+    ctx.accounts.config.accrue_protocol_fee(!is_m, protocol_fee)?;
+    ctx.accounts.config.update_volatility(!is_m, (withdrawn_in, withdrawn_out), swap.amount_in, swap.amount_out, now)?;
+
+    ctx.accounts.burn_lp_tokens(amount)?;
+    ctx.accounts.withdraw_tokens(is_m, quote.amount_out, ctx.remaining_accounts)?;
+
+    let (withdrawn_m, withdrawn_n, amount_m, amount_n) = match is_m {
+        true => (withdrawn_out, withdrawn_in, quote.withdraw_out, quote.withdraw_in),
+        false => (withdrawn_in, withdrawn_out, quote.withdraw_in, quote.withdraw_out),
+    };
+
+    ctx.accounts.vault_m.reload()?;
+    ctx.accounts.vault_n.reload()?;
+    let (reserve_m_after, reserve_n_after) = ctx.accounts.reserves()?;
+    emit_cpi!(events::LiquidityRemoved {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.user.key(),
+        position: None,
+        amount_m,
+        amount_n,
+        liquidity: amount as u128,
+        reserve_m_before: reserve_m,
+        reserve_n_before: reserve_n,
+        reserve_m_after: withdrawn_m,
+        reserve_n_after: withdrawn_n,
+    });
+    emit_cpi!(events::Swap {
+        config: ctx.accounts.config.key(),
+        user: ctx.accounts.user.key(),
+        is_m: !is_m,
+        amount_in: swap.amount_in,
+        amount_out: swap.amount_out,
+        fee: swap.fee,
+        protocol_fee,
+        reserve_m_before: withdrawn_m,
+        reserve_n_before: withdrawn_n,
+        reserve_m_after,
+        reserve_n_after,
+    });
+
+    Ok(())
+}
@@ -1,26 +1,341 @@
 use anchor_lang::prelude::*;
 
 declare_id!("9qXFP6JkCQrTMaGBsMEEitFvaoGYqL4VK4mEYb5WFypi");
+mod clmm;
 mod constants;
+mod curve;
 mod errors;
+mod events;
 mod instructions;
 mod state;
+mod utils;
 
+// Handlers share their names with the #[program] entrypoints; the account structs are what
+// CPI consumers need from this re-export
+#[allow(ambiguous_glob_reexports)]
 pub use instructions::*;
+use state::CurveType;
 
 #[program]
 pub mod amm {
     use super::*;
 
     #[instruction(discriminator = 0)]
-    pub fn initialize(
-        ctx: Context<Initialize>,
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Initialize<'info>>,
         seed: u64,
         authority: Pubkey,
         fee: u16,
         init_m_amount: u64,
         init_n_amount: u64,
+        curve: CurveType,
+        amp: u64,
+        weight_m: u16,
     ) -> Result<()> {
-        instructions::initialize(ctx, seed, authority, fee, init_m_amount, init_n_amount)
+        instructions::initialize(ctx, seed, authority, fee, init_m_amount, init_n_amount, curve, amp, weight_m)
+    }
+
+    #[instruction(discriminator = 1)]
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        is_m: bool,
+        amount_in: u64,
+        min_out: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::swap(ctx, is_m, amount_in, min_out, expiration)
+    }
+
+    #[instruction(discriminator = 2)]
+    pub fn swap_exact_out<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Swap<'info>>,
+        is_m: bool,
+        amount_out: u64,
+        max_in: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::swap_exact_out(ctx, is_m, amount_out, max_in, expiration)
+    }
+
+    #[instruction(discriminator = 3)]
+    pub fn deposit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
+        amount: u64,
+        max_m: u64,
+        max_n: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::deposit(ctx, amount, max_m, max_n, expiration)
+    }
+
+    #[instruction(discriminator = 4)]
+    pub fn withdraw<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Withdraw<'info>>,
+        amount: u64,
+        min_m: u64,
+        min_n: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::withdraw(ctx, amount, min_m, min_n, expiration)
+    }
+
+    #[instruction(discriminator = 5)]
+    pub fn lock(ctx: Context<Update>) -> Result<()> {
+        instructions::lock(ctx)
+    }
+
+    #[instruction(discriminator = 6)]
+    pub fn unlock(ctx: Context<Update>) -> Result<()> {
+        instructions::unlock(ctx)
+    }
+
+    #[instruction(discriminator = 7)]
+    pub fn propose_authority(ctx: Context<Update>, new_authority: Pubkey) -> Result<()> {
+        instructions::propose_authority(ctx, new_authority)
+    }
+
+    #[instruction(discriminator = 8)]
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        instructions::accept_authority(ctx)
+    }
+
+    #[instruction(discriminator = 9)]
+    pub fn renounce_authority(ctx: Context<Update>) -> Result<()> {
+        instructions::renounce_authority(ctx)
+    }
+
+    #[instruction(discriminator = 10)]
+    pub fn update_fee(ctx: Context<Update>, fee: u16, effective_at: i64) -> Result<()> {
+        instructions::update_fee(ctx, fee, effective_at)
+    }
+
+    #[instruction(discriminator = 11)]
+    pub fn apply_fee(ctx: Context<ApplyFee>) -> Result<()> {
+        instructions::apply_fee(ctx)
+    }
+
+    #[instruction(discriminator = 12)]
+    pub fn set_protocol_fee(ctx: Context<UpdateProtocolFee>, protocol_fee: u16, treasury: Pubkey, effective_at: i64) -> Result<()> {
+        instructions::set_protocol_fee(ctx, protocol_fee, treasury, effective_at)
+    }
+
+    #[instruction(discriminator = 13)]
+    pub fn claim_protocol_fees<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimProtocolFees<'info>>,
+    ) -> Result<()> {
+        instructions::claim_protocol_fees(ctx)
+    }
+
+    #[instruction(discriminator = 14)]
+    pub fn create_position(
+        ctx: Context<CreatePosition>,
+        seed: u64,
+        amount: u64,
+        start_ts: i64,
+        cliff_ts: i64,
+        end_ts: i64,
+    ) -> Result<()> {
+        instructions::create_position(ctx, seed, amount, start_ts, cliff_ts, end_ts)
+    }
+
+    #[instruction(discriminator = 15)]
+    pub fn withdraw_position(ctx: Context<WithdrawPosition>, amount: u64) -> Result<()> {
+        instructions::withdraw_position(ctx, amount)
+    }
+
+    #[instruction(discriminator = 16)]
+    pub fn transfer_position(ctx: Context<TransferPosition>, new_owner: Pubkey) -> Result<()> {
+        instructions::transfer_position(ctx, new_owner)
+    }
+
+    #[instruction(discriminator = 17)]
+    pub fn close_position(ctx: Context<ClosePosition>) -> Result<()> {
+        instructions::close_position(ctx)
+    }
+
+    #[instruction(discriminator = 18)]
+    pub fn initialize_oracle(ctx: Context<InitializeOracle>, cardinality: u16) -> Result<()> {
+        instructions::initialize_oracle(ctx, cardinality)
+    }
+
+    #[instruction(discriminator = 19)]
+    pub fn grow_oracle(ctx: Context<GrowOracle>, cardinality_next: u16) -> Result<()> {
+        instructions::grow_oracle(ctx, cardinality_next)
+    }
+
+    #[instruction(discriminator = 20)]
+    pub fn observe(ctx: Context<Observe>, seconds_agos: Vec<u32>) -> Result<Vec<Twap>> {
+        instructions::observe(ctx, seconds_agos)
+    }
+
+    #[instruction(discriminator = 21)]
+    pub fn ramp_amp(ctx: Context<Update>, amp_target: u64, ramp_end_ts: i64) -> Result<()> {
+        instructions::ramp_amp(ctx, amp_target, ramp_end_ts)
+    }
+
+    #[instruction(discriminator = 22)]
+    pub fn stop_ramp_amp(ctx: Context<Update>) -> Result<()> {
+        instructions::stop_ramp_amp(ctx)
+    }
+
+    #[instruction(discriminator = 23)]
+    pub fn initialize_concentrated(
+        ctx: Context<InitializeConcentrated>,
+        seed: u64,
+        authority: Pubkey,
+        fee: u16,
+        tick_spacing: u16,
+        sqrt_price_x64: u128,
+    ) -> Result<()> {
+        instructions::initialize_concentrated(ctx, seed, authority, fee, tick_spacing, sqrt_price_x64)
+    }
+
+    #[instruction(discriminator = 24)]
+    pub fn initialize_tick_array(ctx: Context<InitializeTickArray>, start_tick_index: i32) -> Result<()> {
+        instructions::initialize_tick_array(ctx, start_tick_index)
+    }
+
+    #[instruction(discriminator = 25)]
+    pub fn open_range_position(ctx: Context<OpenRangePosition>, tick_lower: i32, tick_upper: i32) -> Result<()> {
+        instructions::open_range_position(ctx, tick_lower, tick_upper)
+    }
+
+    #[instruction(discriminator = 26)]
+    pub fn increase_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ModifyLiquidity<'info>>,
+        liquidity: u128,
+        max_m: u64,
+        max_n: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::increase_liquidity(ctx, liquidity, max_m, max_n, expiration)
+    }
+
+    #[instruction(discriminator = 27)]
+    pub fn decrease_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ModifyLiquidity<'info>>,
+        liquidity: u128,
+        min_m: u64,
+        min_n: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::decrease_liquidity(ctx, liquidity, min_m, min_n, expiration)
+    }
+
+    #[instruction(discriminator = 28)]
+    pub fn collect_fees<'info>(ctx: Context<'_, '_, 'info, 'info, ModifyLiquidity<'info>>) -> Result<()> {
+        instructions::collect_fees(ctx)
+    }
+
+    #[instruction(discriminator = 29)]
+    pub fn close_range_position(ctx: Context<CloseRangePosition>) -> Result<()> {
+        instructions::close_range_position(ctx)
+    }
+
+    #[instruction(discriminator = 30)]
+    pub fn swap_concentrated<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapConcentrated<'info>>,
+        is_m: bool,
+        amount_in: u64,
+        min_out: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::swap_concentrated(ctx, is_m, amount_in, min_out, expiration)
+    }
+
+    #[instruction(discriminator = 31)]
+    pub fn set_dynamic_fee(ctx: Context<Update>, enabled: bool, fee_min: u16, fee_max: u16, effective_at: i64) -> Result<()> {
+        instructions::set_dynamic_fee(ctx, enabled, fee_min, fee_max, effective_at)
+    }
+
+    #[instruction(discriminator = 32)]
+    pub fn effective_fee(ctx: Context<EffectiveFee>) -> Result<u16> {
+        instructions::effective_fee(ctx)
+    }
+
+    #[instruction(discriminator = 33)]
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+        amount_in: u64,
+        min_out: u64,
+        expiration: i64,
+        hook_accounts: Vec<u8>,
+    ) -> Result<()> {
+        instructions::route_swap(ctx, amount_in, min_out, expiration, hook_accounts)
+    }
+
+    #[instruction(discriminator = 34)]
+    pub fn flash_loan<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashLoan<'info>>,
+        amount_m: u64,
+        amount_n: u64,
+        data: Vec<u8>,
+        hook_accounts: u8,
+    ) -> Result<()> {
+        instructions::flash_loan(ctx, amount_m, amount_n, data, hook_accounts)
+    }
+
+    #[instruction(discriminator = 35)]
+    pub fn zap_in<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Zap<'info>>,
+        is_m: bool,
+        amount_in: u64,
+        min_lp_out: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::zap_in(ctx, is_m, amount_in, min_lp_out, expiration)
+    }
+
+    #[instruction(discriminator = 36)]
+    pub fn zap_out<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Zap<'info>>,
+        is_m: bool,
+        amount: u64,
+        min_out: u64,
+        expiration: i64,
+    ) -> Result<()> {
+        instructions::zap_out(ctx, is_m, amount, min_out, expiration)
+    }
+
+    #[instruction(discriminator = 37)]
+    pub fn initialize_protocol(
+        ctx: Context<InitializeProtocol>,
+        admin: Pubkey,
+        fee_tiers: Vec<u16>,
+        default_protocol_fee: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        instructions::initialize_protocol(ctx, admin, fee_tiers, default_protocol_fee, treasury)
+    }
+
+    #[instruction(discriminator = 38)]
+    pub fn set_fee_tiers(ctx: Context<UpdateProtocol>, fee_tiers: Vec<u16>) -> Result<()> {
+        instructions::set_fee_tiers(ctx, fee_tiers)
+    }
+
+    #[instruction(discriminator = 39)]
+    pub fn set_default_protocol_fee(ctx: Context<UpdateProtocol>, default_protocol_fee: u16) -> Result<()> {
+        instructions::set_default_protocol_fee(ctx, default_protocol_fee)
+    }
+
+    #[instruction(discriminator = 40)]
+    pub fn set_protocol_paused(ctx: Context<UpdateProtocol>, paused: bool) -> Result<()> {
+        instructions::set_protocol_paused(ctx, paused)
+    }
+
+    #[instruction(discriminator = 41)]
+    pub fn propose_protocol_admin(ctx: Context<UpdateProtocol>, new_admin: Pubkey) -> Result<()> {
+        instructions::propose_protocol_admin(ctx, new_admin)
+    }
+
+    #[instruction(discriminator = 42)]
+    pub fn accept_protocol_admin(ctx: Context<AcceptProtocolAdmin>) -> Result<()> {
+        instructions::accept_protocol_admin(ctx)
+    }
+
+    #[instruction(discriminator = 43)]
+    pub fn set_protocol_treasury(ctx: Context<UpdateProtocol>, treasury: Pubkey) -> Result<()> {
+        instructions::set_protocol_treasury(ctx, treasury)
     }
 }
@@ -1,14 +1,678 @@
 use anchor_lang::prelude::*;
+use crate::clmm::{mul_div, sqrt_price_at_tick, Q64};
+use crate::curve::{quote_swap_exact_in, quote_swap_exact_out, spot_price, Curve, FEE_DENOMINATOR};
+use crate::constants::{FEE_UPDATE_DELAY, MAX_FEE_TIERS, VOLATILITY_FEE_DIVISOR, VOLATILITY_HALF_LIFE, WEIGHT_DENOMINATOR};
+use crate::errors::AmmError;
+
+/// Invariant a pool prices swaps with, fixed at `initialize`.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CurveType {
+    ConstantProduct, // x * y = k
+    StableSwap,      // Curve-style invariant for pegged pairs, flattened by the amplification coefficient
+    Weighted,        // x^w_m * y^w_n = k, with the weights fixed by `weight_m`
+    Concentrated,    // Liquidity provided over tick ranges, see `RangePosition`
+}
+
+/// Dynamic fee settings queued by `set_dynamic_fee` until the fee timelock passes.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DynamicFee {
+    pub enabled: bool,
+    pub fee_min: u16,
+    pub fee_max: u16,
+}
 
 #[account(discriminator = 1)]
 #[derive(InitSpace)]
 pub struct Config {
     pub seed: u64,
-    pub authority: Pubkey,
+    pub authority: Option<Pubkey>, // None once the authority is renounced and the pool is immutable
+    pub pending_authority: Option<Pubkey>, // Proposed authority waiting to accept the handover
     pub mint_m: Pubkey, // Mint address for token M
     pub mint_n: Pubkey, // Mint address for token N
-    pub fee: u16,       // Fee charged on swaps, in basis points (1/100th of a percent)
-    pub locked: bool,
+    pub fee: u16,       // Fee charged on swaps (the base fee in dynamic mode), in basis points (1/100th of a percent)
+    pub pending_fee: Option<u16>, // Queued fee, applied once `fee_effective_at` has passed
+    pub fee_effective_at: i64,
+    pub protocol_fee: u16,      // Share of `fee` paid to the treasury, in basis points of the fee
+    pub treasury: Pubkey,       // Owner of the token accounts that receive protocol fees
+    pub pending_protocol_fee: Option<u16>, // Queued protocol fee and treasury, applied with any pending fee
+    pub pending_treasury: Option<Pubkey>,
+    pub protocol_fees_m: u64,   // Accrued protocol fees held in vault_m, excluded from reserves
+    pub protocol_fees_n: u64,   // Accrued protocol fees held in vault_n, excluded from reserves
+    pub price_m_cumulative: u128, // Sum of (price of M in N, Q64.64) * seconds, wraps on overflow
+    pub price_n_cumulative: u128, // Sum of (price of N in M, Q64.64) * seconds, wraps on overflow
+    pub last_update_ts: i64,      // When the cumulative prices were last advanced
+    pub oracle_initialized: bool, // Set once the observation buffer exists; reserve changes must then record into it
+    pub curve: CurveType,
+    pub amp_initial: u64,         // StableSwap amplification at `amp_ramp_start_ts`
+    pub amp_target: u64,          // StableSwap amplification from `amp_ramp_end_ts` onwards
+    pub amp_ramp_start_ts: i64,
+    pub amp_ramp_end_ts: i64,
+    pub weight_m: u16,            // Share of pool value held in M, in basis points; N holds the rest
+    pub tick_spacing: u16,        // Concentrated pools: ticks usable as range bounds are multiples of this
+    pub tick_current: i32,        // Concentrated pools: greatest tick at or below the current price
+    pub sqrt_price_x64: u128,     // Concentrated pools: sqrt of the price of M in N, Q64.64
+    pub liquidity: u128,          // Concentrated pools: liquidity of the ranges containing the current price
+    pub fee_growth_global_m_x64: u128, // Concentrated pools: LP fees in M earned per unit of liquidity, Q64.64, wraps
+    pub fee_growth_global_n_x64: u128, // Concentrated pools: LP fees in N earned per unit of liquidity, Q64.64, wraps
+    pub dynamic_fee: bool,        // Swap fee follows recent volatility, within [fee_min, fee_max], instead of staying at `fee`
+    pub fee_min: u16,
+    pub fee_max: u16,
+    pub pending_dynamic_fee: Option<DynamicFee>, // Queued dynamic fee settings, applied with any pending fee
+    pub volatility: u64,          // Recent price movement in parts per million, decaying towards 0 over time
+    pub volatility_updated_ts: i64,
+    pub locked: bool,             // Stops swaps and deposits; LPs can still withdraw
     pub bump_lp: u8,
     pub bump: u8,
 }
+
+impl Config {
+    /// Seeds of the config PDA, which signs for the vaults and the LP mint.
+    pub fn signer_seeds<'a>(&'a self, seed_bytes: &'a [u8; 8]) -> [&'a [u8]; 5] {
+        [
+            b"config",
+            seed_bytes,
+            self.mint_m.as_ref(),
+            self.mint_n.as_ref(),
+            std::slice::from_ref(&self.bump),
+        ]
+    }
+
+    /// Pool reserves available to LPs and traders: vault balances minus unclaimed protocol fees.
+    pub fn reserves(&self, vault_m_amount: u64, vault_n_amount: u64) -> Result<(u64, u64)> {
+        let reserve_m = vault_m_amount
+            .checked_sub(self.protocol_fees_m)
+            .ok_or(AmmError::Underflow)?;
+        let reserve_n = vault_n_amount
+            .checked_sub(self.protocol_fees_n)
+            .ok_or(AmmError::Underflow)?;
+        Ok((reserve_m, reserve_n))
+    }
+
+    /// Records the treasury's cut of a swap fee paid in token M (`is_m`) or token N.
+    pub fn accrue_protocol_fee(&mut self, is_m: bool, amount: u64) -> Result<()> {
+        let accrued = match is_m {
+            true => &mut self.protocol_fees_m,
+            false => &mut self.protocol_fees_n,
+        };
+        *accrued = accrued.checked_add(amount).ok_or(AmmError::Overflow)?;
+        Ok(())
+    }
+
+    /// Cumulative prices extrapolated to `now`, assuming the reserves have not changed
+    /// since `last_update_ts`.
+    pub fn cumulative_prices(&self, reserve_m: u64, reserve_n: u64, now: i64) -> Result<(u128, u128)> {
+        let elapsed = now.saturating_sub(self.last_update_ts);
+        let priced = match self.curve {
+            CurveType::Concentrated => self.sqrt_price_x64 > 0,
+            _ => reserve_m > 0 && reserve_n > 0,
+        };
+        if elapsed <= 0 || !priced {
+            return Ok((self.price_m_cumulative, self.price_n_cumulative));
+        }
+
+        let (price_m, price_n) = self.spot_prices(reserve_m, reserve_n, now)?;
+        Ok((
+            self.price_m_cumulative.wrapping_add(price_m.wrapping_mul(elapsed as u128)),
+            self.price_n_cumulative.wrapping_add(price_n.wrapping_mul(elapsed as u128)),
+        ))
+    }
+
+    /// Marginal prices of M in N and of N in M as Q64.64 numbers, under the pool's curve.
+    pub fn spot_prices(&self, reserve_m: u64, reserve_n: u64, now: i64) -> Result<(u128, u128)> {
+        match self.curve {
+            CurveType::Concentrated => {
+                let price_m = mul_div(self.sqrt_price_x64, self.sqrt_price_x64, Q64, false)?;
+                Ok((price_m, mul_div(Q64, Q64, price_m, false)?))
+            }
+            _ => Ok((
+                spot_price(self.pricing_curve(true, now)?, reserve_m, reserve_n)?,
+                spot_price(self.pricing_curve(false, now)?, reserve_n, reserve_m)?,
+            )),
+        }
+    }
+
+    /// Curve parameters at `now` for a swap paying in token M (`is_m`) or token N.
+    /// Concentrated pools price through their ticks instead.
+    pub fn pricing_curve(&self, is_m: bool, now: i64) -> Result<Curve> {
+        match self.curve {
+            CurveType::ConstantProduct => Ok(Curve::ConstantProduct),
+            CurveType::StableSwap => Ok(Curve::StableSwap { amp: self.amp(now) }),
+            CurveType::Weighted => {
+                let (weight_in, weight_out) = self.weights(is_m);
+                Ok(Curve::Weighted { weight_in, weight_out })
+            }
+            CurveType::Concentrated => err!(AmmError::InvalidCurve),
+        }
+    }
+
+    /// Returns the (input, output) weights for a swap paying in token M when `is_m` is set.
+    pub fn weights(&self, is_m: bool) -> (u16, u16) {
+        let weight_n = WEIGHT_DENOMINATOR - self.weight_m;
+        match is_m {
+            true => (self.weight_m, weight_n),
+            false => (weight_n, self.weight_m),
+        }
+    }
+
+    /// StableSwap amplification coefficient at `now`, moving linearly from `amp_initial`
+    /// to `amp_target` while a ramp is in progress.
+    pub fn amp(&self, now: i64) -> u64 {
+        if now >= self.amp_ramp_end_ts {
+            return self.amp_target;
+        }
+        if now <= self.amp_ramp_start_ts {
+            return self.amp_initial;
+        }
+
+        let elapsed = (now - self.amp_ramp_start_ts) as u128;
+        let duration = (self.amp_ramp_end_ts - self.amp_ramp_start_ts) as u128;
+        let (initial, target) = (self.amp_initial as u128, self.amp_target as u128);
+        let amp = match target > initial {
+            true => initial + (target - initial) * elapsed / duration,
+            false => initial - (initial - target) * elapsed / duration,
+        };
+
+        amp as u64
+    }
+
+    /// Volatility measure at `now`: it halves every VOLATILITY_HALF_LIFE seconds without trading.
+    pub fn volatility(&self, now: i64) -> u64 {
+        let halvings = now.saturating_sub(self.volatility_updated_ts) / VOLATILITY_HALF_LIFE;
+        self.volatility.checked_shr(halvings as u32).unwrap_or(0)
+    }
+
+    /// Swap fee charged at `now`. In dynamic mode the base `fee` is raised by the recent
+    /// volatility and then held within [fee_min, fee_max].
+    pub fn effective_fee(&self, now: i64) -> u16 {
+        if !self.dynamic_fee {
+            return self.fee;
+        }
+
+        let surcharge = self.volatility(now) / VOLATILITY_FEE_DIVISOR;
+        let fee = (self.fee as u64).saturating_add(surcharge);
+        fee.clamp(self.fee_min as u64, self.fee_max as u64) as u16
+    }
+
+    /// Adds the relative move between two prices of M in N to the volatility measure.
+    pub fn record_price_move(&mut self, price_before: u128, price_after: u128, now: i64) -> Result<()> {
+        let price_move = match price_before {
+            0 => 0,
+            _ => mul_div(price_before.abs_diff(price_after), 1_000_000, price_before, false)?,
+        };
+
+        self.volatility = self.volatility(now).saturating_add(u64::try_from(price_move).unwrap_or(u64::MAX));
+        self.volatility_updated_ts = now;
+        Ok(())
+    }
+
+    /// Feeds the move in the price of M caused by a swap into the dynamic fee's volatility
+    /// measure. Takes the reserves from before the swap; must run after the fee is charged.
+    pub fn update_volatility(
+        &mut self,
+        is_m: bool,
+        (reserve_in, reserve_out): (u64, u64),
+        amount_in: u64,
+        amount_out: u64,
+        now: i64,
+    ) -> Result<()> {
+        if !self.dynamic_fee {
+            return Ok(());
+        }
+
+        let reserve_in_after = reserve_in.checked_add(amount_in).ok_or(AmmError::Overflow)?;
+        let reserve_out_after = reserve_out.checked_sub(amount_out).ok_or(AmmError::Underflow)?;
+        let (before, after) = match is_m {
+            true => ((reserve_in, reserve_out), (reserve_in_after, reserve_out_after)),
+            false => ((reserve_out, reserve_in), (reserve_out_after, reserve_in_after)),
+        };
+
+        let (price_before, _) = self.spot_prices(before.0, before.1, now)?;
+        let (price_after, _) = self.spot_prices(after.0, after.1, now)?;
+        self.record_price_move(price_before, price_after, now)
+    }
+
+    /// Quotes an exact-input swap paying in token M (`is_m`) or token N against the pool's curve,
+    /// after the pool fee.
+    pub fn quote_amount_out(&self, is_m: bool, reserve_in: u64, reserve_out: u64, amount_in: u64, now: i64) -> Result<u64> {
+        let quote = quote_swap_exact_in(self.pricing_curve(is_m, now)?, reserve_in, reserve_out, amount_in, self.effective_fee(now))?;
+        Ok(quote.amount_out)
+    }
+
+    /// Quotes the input, fee included, needed to pay out exactly `amount_out` under the pool's curve.
+    pub fn quote_amount_in(&self, is_m: bool, reserve_in: u64, reserve_out: u64, amount_out: u64, now: i64) -> Result<u64> {
+        let quote = quote_swap_exact_out(self.pricing_curve(is_m, now)?, reserve_in, reserve_out, amount_out, self.effective_fee(now))?;
+        Ok(quote.amount_in)
+    }
+
+    /// Advances the TWAP accumulators by the time elapsed since the last update, priced at the
+    /// reserves held during that time. Must run before an instruction changes the reserves.
+    /// A TWAP between two observations is (cumulative_2 - cumulative_1) / (t_2 - t_1),
+    /// using wrapping subtraction.
+    pub fn update_oracle(&mut self, reserve_m: u64, reserve_n: u64, now: i64) -> Result<()> {
+        (self.price_m_cumulative, self.price_n_cumulative) = self.cumulative_prices(reserve_m, reserve_n, now)?;
+        self.last_update_ts = now;
+        Ok(())
+    }
+
+    /// Once the pool has an observation buffer, instructions that change the reserves must be
+    /// given it, so observations can't be skipped by leaving the optional account out.
+    pub fn check_oracle<T>(&self, oracle: Option<&T>) -> Result<()> {
+        require!(oracle.is_some() || !self.oracle_initialized, AmmError::OracleRequired);
+        Ok(())
+    }
+
+    /// Fee growth per unit of liquidity earned inside [tick_lower, tick_upper), as of now.
+    pub fn fee_growth_inside(&self, tick_lower: i32, lower: &Tick, tick_upper: i32, upper: &Tick) -> (u128, u128) {
+        let inside = |global: u128, lower_outside: u128, upper_outside: u128| {
+            let below = match self.tick_current >= tick_lower {
+                true => lower_outside,
+                false => global.wrapping_sub(lower_outside),
+            };
+            let above = match self.tick_current < tick_upper {
+                true => upper_outside,
+                false => global.wrapping_sub(upper_outside),
+            };
+            global.wrapping_sub(below).wrapping_sub(above)
+        };
+
+        (
+            inside(self.fee_growth_global_m_x64, lower.fee_growth_outside_m_x64, upper.fee_growth_outside_m_x64),
+            inside(self.fee_growth_global_n_x64, lower.fee_growth_outside_n_x64, upper.fee_growth_outside_n_x64),
+        )
+    }
+
+    /// Whether any fee setting is queued behind the timelock.
+    pub fn has_pending_fee_change(&self) -> bool {
+        self.pending_fee.is_some() || self.pending_protocol_fee.is_some() || self.pending_dynamic_fee.is_some()
+    }
+
+    /// Moves the queued fee settings to `effective_at`, which must be at least FEE_UPDATE_DELAY
+    /// from now. Call before queuing the new setting: settings already queued keep their
+    /// announced time or a later one, so queuing another change never brings them forward.
+    pub fn schedule_fee_change(&mut self, effective_at: i64, now: i64) -> Result<()> {
+        let earliest = now.checked_add(FEE_UPDATE_DELAY).ok_or(AmmError::Overflow)?;
+        require!(effective_at >= earliest, AmmError::FeeTimelockNotExpired);
+
+        self.fee_effective_at = match self.has_pending_fee_change() {
+            true => effective_at.max(self.fee_effective_at),
+            false => effective_at,
+        };
+        Ok(())
+    }
+
+    /// Drops every queued fee setting.
+    pub fn clear_pending_fee_change(&mut self) {
+        self.pending_fee = None;
+        self.pending_protocol_fee = None;
+        self.pending_treasury = None;
+        self.pending_dynamic_fee = None;
+    }
+
+    /// Fails unless `signer` is the current authority of a pool that has not been renounced.
+    pub fn check_authority(&self, signer: &Pubkey) -> Result<()> {
+        let authority = self.authority.ok_or(AmmError::AmmIsImmutable)?;
+        require_keys_eq!(authority, *signer, AmmError::InvalidAuthority);
+        Ok(())
+    }
+}
+
+/// LP tokens locked under a vesting schedule. The LP sits in the position's own token
+/// account and unlocks linearly from `start_ts` to `end_ts`, with nothing available before `cliff_ts`.
+#[account(discriminator = 2)]
+#[derive(InitSpace)]
+pub struct Position {
+    pub owner: Pubkey,         // Current holder, allowed to withdraw unlocked LP
+    pub config: Pubkey,        // Pool the LP belongs to
+    pub creator: Pubkey,       // Part of the PDA seeds, unchanged when ownership moves
+    pub seed: u64,
+    pub total_amount: u64,     // LP locked when the position was created
+    pub withdrawn_amount: u64, // LP already released to the owner
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+    pub bump: u8,
+}
+
+impl Position {
+    /// Seeds of the position PDA, which signs for the LP held in its token account.
+    pub fn signer_seeds<'a>(&'a self, seed_bytes: &'a [u8; 8]) -> [&'a [u8]; 5] {
+        [
+            b"position",
+            self.config.as_ref(),
+            self.creator.as_ref(),
+            seed_bytes,
+            std::slice::from_ref(&self.bump),
+        ]
+    }
+
+    /// Total LP vested at `now`, including anything already withdrawn.
+    pub fn vested_amount(&self, now: i64) -> Result<u64> {
+        if now < self.cliff_ts {
+            return Ok(0);
+        }
+        if now >= self.end_ts {
+            return Ok(self.total_amount);
+        }
+
+        let elapsed = (now - self.start_ts) as u128;
+        let duration = (self.end_ts - self.start_ts) as u128;
+        let vested = (self.total_amount as u128)
+            .checked_mul(elapsed)
+            .ok_or(AmmError::Overflow)?
+            / duration;
+
+        Ok(vested as u64)
+    }
+
+    /// LP the owner can withdraw right now.
+    pub fn unlocked_amount(&self, now: i64) -> Result<u64> {
+        self.vested_amount(now)?
+            .checked_sub(self.withdrawn_amount)
+            .ok_or(AmmError::Underflow.into())
+    }
+}
+
+/// Timestamped snapshot of a pool's cumulative prices.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct Observation {
+    pub timestamp: i64, // Zero for slots that have never been written
+    pub price_m_cumulative: u128,
+    pub price_n_cumulative: u128,
+}
+
+impl Observation {
+    pub const SIZE: usize = 8 + 16 + 16;
+}
+
+/// Ring buffer of observations for one pool. Only this header is (de)serialized by Anchor;
+/// the `cardinality_next` observation slots follow it in the account data and are
+/// read and written in place, so large buffers never have to fit on the heap.
+#[account(discriminator = 3)]
+#[derive(InitSpace)]
+pub struct Oracle {
+    pub config: Pubkey,
+    pub index: u16,            // Slot holding the most recent observation
+    pub cardinality: u16,      // Number of slots currently in the ring
+    pub cardinality_next: u16, // Allocated slots; the ring grows into them when it wraps
+    pub bump: u8,
+}
+
+impl Oracle {
+    pub const HEADER_SIZE: usize = Oracle::DISCRIMINATOR.len() + Oracle::INIT_SPACE;
+
+    /// Account size needed to hold `cardinality` observations.
+    pub fn space(cardinality: u16) -> usize {
+        Self::HEADER_SIZE + cardinality as usize * Observation::SIZE
+    }
+
+    pub fn read(info: &AccountInfo, slot: u16) -> Result<Observation> {
+        let offset = Self::HEADER_SIZE + slot as usize * Observation::SIZE;
+        let data = info.try_borrow_data()?;
+        let mut bytes = data
+            .get(offset..offset + Observation::SIZE)
+            .ok_or(AmmError::InvalidCardinality)?;
+        Ok(Observation::deserialize(&mut bytes)?)
+    }
+
+    pub fn write(info: &AccountInfo, slot: u16, observation: &Observation) -> Result<()> {
+        let offset = Self::HEADER_SIZE + slot as usize * Observation::SIZE;
+        let mut data = info.try_borrow_mut_data()?;
+        let mut bytes = data
+            .get_mut(offset..offset + Observation::SIZE)
+            .ok_or(AmmError::InvalidCardinality)?;
+        observation.serialize(&mut bytes)?;
+        Ok(())
+    }
+
+    /// Appends the pool's current cumulative prices, at most once per second.
+    /// The ring only grows into newly allocated slots once the write index wraps,
+    /// so observations always stay in chronological order.
+    pub fn record(&mut self, info: &AccountInfo, config: &Config) -> Result<()> {
+        let last = Self::read(info, self.index)?;
+        if last.timestamp == config.last_update_ts {
+            return Ok(());
+        }
+
+        if self.index == self.cardinality - 1 && self.cardinality_next > self.cardinality {
+            self.cardinality = self.cardinality_next;
+        }
+        self.index = (self.index + 1) % self.cardinality;
+
+        Self::write(info, self.index, &Observation {
+            timestamp: config.last_update_ts,
+            price_m_cumulative: config.price_m_cumulative,
+            price_n_cumulative: config.price_n_cumulative,
+        })
+    }
+
+    /// Cumulative prices at `target`, interpolated between the surrounding observations.
+    /// `latest` is the exact cumulative price at the newest known time and must not be
+    /// older than the newest observation.
+    pub fn cumulative_prices_at(&self, info: &AccountInfo, target: i64, latest: &Observation) -> Result<(u128, u128)> {
+        if target >= latest.timestamp {
+            return Ok((latest.price_m_cumulative, latest.price_n_cumulative));
+        }
+
+        // Until the ring has wrapped, unwritten slots sit after the index and slot 0 is the oldest
+        let next = (self.index + 1) % self.cardinality;
+        let (oldest, len) = match Self::read(info, next)?.timestamp {
+            0 => (0, self.index + 1),
+            _ => (next, self.cardinality),
+        };
+        let at = |i: u16| Self::read(info, ((oldest as u32 + i as u32) % self.cardinality as u32) as u16);
+
+        let first = at(0)?;
+        require!(target >= first.timestamp, AmmError::ObservationTooOld);
+
+        // Binary search for the last observation at or before `target`
+        let (mut lo, mut hi) = (0u16, len - 1);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            if at(mid)?.timestamp <= target {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+
+        let before = at(lo)?;
+        let after = match lo + 1 < len {
+            true => at(lo + 1)?,
+            false => *latest,
+        };
+        if before.timestamp == target {
+            return Ok((before.price_m_cumulative, before.price_n_cumulative));
+        }
+
+        // Cumulative prices grow linearly between two observations at the average price over the gap
+        let span = (after.timestamp - before.timestamp) as u128;
+        let offset = (target - before.timestamp) as u128;
+        let interpolate = |from: u128, to: u128| from.wrapping_add(to.wrapping_sub(from) / span * offset);
+
+        Ok((
+            interpolate(before.price_m_cumulative, after.price_m_cumulative),
+            interpolate(before.price_n_cumulative, after.price_n_cumulative),
+        ))
+    }
+}
+
+/// Number of ticks covered by one tick array.
+pub const TICK_ARRAY_SIZE: usize = 60;
+
+/// Liquidity boundary at one tick of a concentrated pool.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace, Clone, Copy, Default)]
+pub struct Tick {
+    pub liquidity_net: i128,   // Added to the pool's liquidity when the price crosses this tick upwards
+    pub liquidity_gross: u128, // Total liquidity referencing this tick; zero means uninitialized
+    pub fee_growth_outside_m_x64: u128, // Fee growth on the side of this tick away from the current price
+    pub fee_growth_outside_n_x64: u128,
+}
+
+impl Tick {
+    /// Adds `delta` of liquidity to a range bounded below (or, with `upper`, above) by this tick.
+    /// A newly used tick at or below the current price assumes all fee growth so far happened below it.
+    pub fn update_liquidity(&mut self, tick: i32, config: &Config, delta: i128, upper: bool) -> Result<()> {
+        if self.liquidity_gross == 0 && tick <= config.tick_current {
+            self.fee_growth_outside_m_x64 = config.fee_growth_global_m_x64;
+            self.fee_growth_outside_n_x64 = config.fee_growth_global_n_x64;
+        }
+
+        self.liquidity_gross = self.liquidity_gross
+            .checked_add_signed(delta)
+            .ok_or(AmmError::Overflow)?;
+        self.liquidity_net = match upper {
+            true => self.liquidity_net.checked_sub(delta),
+            false => self.liquidity_net.checked_add(delta),
+        }
+        .ok_or(AmmError::Overflow)?;
+        Ok(())
+    }
+
+    /// Moves the price across this tick, flipping which side its fee growth is measured on.
+    /// Returns the liquidity delta to apply in the direction of travel.
+    pub fn cross(&mut self, fee_growth_global_m_x64: u128, fee_growth_global_n_x64: u128, downwards: bool) -> i128 {
+        self.fee_growth_outside_m_x64 = fee_growth_global_m_x64.wrapping_sub(self.fee_growth_outside_m_x64);
+        self.fee_growth_outside_n_x64 = fee_growth_global_n_x64.wrapping_sub(self.fee_growth_outside_n_x64);
+        match downwards {
+            true => -self.liquidity_net,
+            false => self.liquidity_net,
+        }
+    }
+}
+
+/// A run of TICK_ARRAY_SIZE consecutive usable ticks of a concentrated pool, starting at
+/// `start_tick_index`. Ticks are created on demand, one array at a time.
+#[account(discriminator = 4)]
+#[derive(InitSpace)]
+pub struct TickArray {
+    pub config: Pubkey,
+    pub start_tick_index: i32,
+    pub ticks: [Tick; TICK_ARRAY_SIZE],
+}
+
+impl TickArray {
+    /// Number of tick indexes spanned by one array.
+    pub fn span(tick_spacing: u16) -> i32 {
+        tick_spacing as i32 * TICK_ARRAY_SIZE as i32
+    }
+
+    /// Start index of the array holding `tick`.
+    pub fn start_index_for(tick: i32, tick_spacing: u16) -> i32 {
+        tick.div_euclid(Self::span(tick_spacing)) * Self::span(tick_spacing)
+    }
+
+    pub fn contains(&self, tick: i32, tick_spacing: u16) -> bool {
+        tick >= self.start_tick_index && tick < self.start_tick_index + Self::span(tick_spacing)
+    }
+
+    pub fn tick_mut(&mut self, tick: i32, tick_spacing: u16) -> Result<&mut Tick> {
+        require!(self.contains(tick, tick_spacing), AmmError::InvalidTickArray);
+        require!(tick % tick_spacing as i32 == 0, AmmError::InvalidTick);
+        let offset = ((tick - self.start_tick_index) / tick_spacing as i32) as usize;
+        Ok(&mut self.ticks[offset])
+    }
+}
+
+/// Liquidity provided over [tick_lower, tick_upper) of a concentrated pool. It only earns fees,
+/// and only holds both tokens, while the current price is inside the range.
+#[account(discriminator = 5)]
+#[derive(InitSpace)]
+pub struct RangePosition {
+    pub owner: Pubkey,
+    pub config: Pubkey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub fee_growth_inside_m_last_x64: u128, // Fee growth inside the range when fees were last settled
+    pub fee_growth_inside_n_last_x64: u128,
+    pub fees_owed_m: u64, // Settled fees not yet collected
+    pub fees_owed_n: u64,
+    pub bump: u8,
+}
+
+impl RangePosition {
+    /// Credits the fees earned since the last settlement, given the current fee growth inside the range.
+    pub fn settle_fees(&mut self, fee_growth_inside_m_x64: u128, fee_growth_inside_n_x64: u128) -> Result<()> {
+        let earned = |inside: u128, last: u128| -> Result<u64> {
+            let earned = mul_div(inside.wrapping_sub(last), self.liquidity, Q64, false)?;
+            u64::try_from(earned).map_err(|_| AmmError::Overflow.into())
+        };
+        let earned_m = earned(fee_growth_inside_m_x64, self.fee_growth_inside_m_last_x64)?;
+        let earned_n = earned(fee_growth_inside_n_x64, self.fee_growth_inside_n_last_x64)?;
+
+        self.fees_owed_m = self.fees_owed_m.checked_add(earned_m).ok_or(AmmError::Overflow)?;
+        self.fees_owed_n = self.fees_owed_n.checked_add(earned_n).ok_or(AmmError::Overflow)?;
+        self.fee_growth_inside_m_last_x64 = fee_growth_inside_m_x64;
+        self.fee_growth_inside_n_last_x64 = fee_growth_inside_n_x64;
+        Ok(())
+    }
+
+    /// Sqrt prices at the range bounds.
+    pub fn sqrt_prices(&self) -> Result<(u128, u128)> {
+        Ok((sqrt_price_at_tick(self.tick_lower)?, sqrt_price_at_tick(self.tick_upper)?))
+    }
+}
+
+/// The one pool for an ordered mint pair and fee tier, at the PDA ["pair", mint_m, mint_n, fee].
+/// The tier is the fee the pool was created with; later fee updates do not move it.
+#[account(discriminator = 6)]
+#[derive(InitSpace)]
+pub struct PairRegistry {
+    pub mint_m: Pubkey,
+    pub mint_n: Pubkey,
+    pub fee: u16,
+    pub config: Pubkey,
+    pub bump: u8,
+}
+
+/// Program-wide settings at the PDA ["protocol"]. Pools can only be created at one of the
+/// allowed fee tiers and start with the default protocol fee share. While paused, no pools can be
+/// created and swaps, deposits and flash loans fail; withdrawals stay open.
+#[account(discriminator = 7)]
+#[derive(InitSpace)]
+pub struct ProtocolConfig {
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    #[max_len(MAX_FEE_TIERS)]
+    pub fee_tiers: Vec<u16>, // Allowed pool fees in basis points, strictly increasing
+    pub default_protocol_fee: u16, // Treasury's share of swap fees given to new pools, in basis points of the fee
+    pub treasury: Pubkey, // Where new pools pay out claimed protocol fees
+    pub paused: bool,
+    pub bump: u8,
+}
+
+impl ProtocolConfig {
+    pub fn check_admin(&self, signer: &Pubkey) -> Result<()> {
+        require_keys_eq!(self.admin, *signer, AmmError::InvalidAuthority);
+        Ok(())
+    }
+
+    pub fn check_not_paused(&self) -> Result<()> {
+        require!(!self.paused, AmmError::ProtocolPaused);
+        Ok(())
+    }
+
+    pub fn check_fee_tier(&self, fee: u16) -> Result<()> {
+        require!(self.fee_tiers.contains(&fee), AmmError::InvalidFeeTier);
+        Ok(())
+    }
+
+    /// Largest fee a pool can be created at, which also caps a dynamic fee.
+    pub fn max_fee_tier(&self) -> u16 {
+        self.fee_tiers.last().copied().unwrap_or_default()
+    }
+
+    /// Checks a new list of fee tiers before it replaces the current one.
+    pub fn validate_fee_tiers(fee_tiers: &[u16]) -> Result<()> {
+        require!(
+            !fee_tiers.is_empty() && fee_tiers.len() <= MAX_FEE_TIERS,
+            AmmError::InvalidFeeTiers
+        );
+        require!(
+            fee_tiers.iter().all(|&fee| fee > 0 && (fee as u128) < FEE_DENOMINATOR),
+            AmmError::InvalidFeeTiers
+        );
+        require!(fee_tiers.windows(2).all(|pair| pair[0] < pair[1]), AmmError::InvalidFeeTiers);
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022;
+use anchor_spl::token_2022::spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anchor_spl::token_2022::spl_token_2022::onchain::invoke_transfer_checked;
+use crate::errors::AmmError;
+
+/// Moves `amount` tokens between two token accounts with `transfer_checked`.
+/// Pass `signer_seeds` when the authority is the config PDA. For Token-2022 mints with a
+/// transfer hook, `additional_accounts` must hold the hook's extra accounts, which are
+/// resolved from the mint's validation account and forwarded to the CPI.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_tokens<'info>(
+    token_program: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    amount: u64,
+    decimals: u8,
+    signer_seeds: &[&[&[u8]]],
+    additional_accounts: &[AccountInfo<'info>],
+) -> Result<()> {
+    invoke_transfer_checked(
+        token_program.key,
+        from,
+        mint,
+        to,
+        authority,
+        additional_accounts,
+        amount,
+        decimals,
+        signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+/// Reads the Token-2022 transfer-fee extension of `mint`, if it has one.
+fn transfer_fee_config(mint: &AccountInfo) -> Result<Option<TransferFeeConfig>> {
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(None);
+    }
+
+    let data = mint.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(state.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+/// Amount that lands in the destination when `amount` is sent, after the mint's transfer fee.
+pub fn net_amount(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    let Some(config) = transfer_fee_config(mint)? else {
+        return Ok(amount);
+    };
+
+    let fee = config
+        .calculate_epoch_fee(Clock::get()?.epoch, amount)
+        .ok_or(AmmError::Overflow)?;
+    amount.checked_sub(fee).ok_or(AmmError::Underflow.into())
+}
+
+/// Amount that has to be sent so that `net` lands in the destination after the mint's transfer fee.
+pub fn gross_amount(mint: &AccountInfo, net: u64) -> Result<u64> {
+    let Some(config) = transfer_fee_config(mint)? else {
+        return Ok(net);
+    };
+    if net == 0 {
+        return Ok(0);
+    }
+
+    let fee = config
+        .calculate_inverse_epoch_fee(Clock::get()?.epoch, net)
+        .ok_or(AmmError::Overflow)?;
+    net.checked_add(fee).ok_or(AmmError::Overflow.into())
+}
@@ -0,0 +1,86 @@
+//! Token-2022 transfer hook the amm tests attach to a mint. Every hooked transfer bumps a
+//! counter held in an extra account, so a test can tell the hook ran with the extra accounts
+//! resolved from the mint's ExtraAccountMetaList.
+
+use anchor_lang::prelude::*;
+use spl_discriminator::SplDiscriminate;
+use spl_tlv_account_resolution::{account::ExtraAccountMeta, seeds::Seed, state::ExtraAccountMetaList};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+
+declare_id!("8CvDus6fMsy2XDuoeRTp5XjNx3J5ozJbMxrQp8Zs6iFn");
+
+#[program]
+pub mod transfer_hook_fixture {
+    use super::*;
+
+    /// Writes the mint's validation account: one extra account, the writable counter PDA.
+    #[instruction(discriminator = 0)]
+    pub fn initialize_extra_account_meta_list(ctx: Context<InitializeExtraAccountMetaList>) -> Result<()> {
+        let extra_account_metas = [ExtraAccountMeta::new_with_seeds(
+            &[Seed::Literal { bytes: b"counter".to_vec() }],
+            false,
+            true,
+        )?];
+        let mut data = ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?;
+        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut data, &extra_account_metas)?;
+
+        Ok(())
+    }
+
+    #[instruction(discriminator = ExecuteInstruction::SPL_DISCRIMINATOR_SLICE)]
+    pub fn execute(ctx: Context<Execute>, _amount: u64) -> Result<()> {
+        ctx.accounts.counter.transfers += 1;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeExtraAccountMetaList<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Written by `ExtraAccountMetaList::init`
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"extra-account-metas", mint.key().as_ref()],
+        bump,
+        space = ExtraAccountMetaList::size_of(1)?,
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    /// CHECK: Only used as a seed
+    pub mint: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [b"counter"],
+        bump,
+        space = Counter::DISCRIMINATOR.len() + Counter::INIT_SPACE,
+    )]
+    pub counter: Account<'info, Counter>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Accounts Token-2022 passes to the hook, in the order of the transfer-hook interface.
+#[derive(Accounts)]
+pub struct Execute<'info> {
+    /// CHECK: Source token account
+    pub source: UncheckedAccount<'info>,
+    /// CHECK: Hooked mint
+    pub mint: UncheckedAccount<'info>,
+    /// CHECK: Destination token account
+    pub destination: UncheckedAccount<'info>,
+    /// CHECK: Transfer authority
+    pub owner: UncheckedAccount<'info>,
+    /// CHECK: The mint's validation account
+    #[account(seeds = [b"extra-account-metas", mint.key().as_ref()], bump)]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"counter"], bump)]
+    pub counter: Account<'info, Counter>,
+}
+
+#[account(discriminator = 1)]
+#[derive(InitSpace)]
+pub struct Counter {
+    pub transfers: u64,
+}
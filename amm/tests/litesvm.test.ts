@@ -1,9 +1,9 @@
 import { expect } from "chai";
-import { LiteSVM } from "litesvm";
+import { FailedTransactionMetadata, LiteSVM } from "litesvm";
 import anchor from "@coral-xyz/anchor";
 import Idl from "../target/idl/amm.json" with {type: "json"};
 import { Keypair, PublicKey, SystemProgram, Transaction, TransactionInstruction } from "@solana/web3.js";
-import { ACCOUNT_SIZE, AccountLayout, ASSOCIATED_TOKEN_PROGRAM_ID, getAssociatedTokenAddressSync, MINT_SIZE, MintLayout, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { ACCOUNT_SIZE, AccountLayout, ASSOCIATED_TOKEN_PROGRAM_ID, createTransferInstruction, getAssociatedTokenAddressSync, MINT_SIZE, MintLayout, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID } from "@solana/spl-token";
 
 
 describe("LiteSVM", () => {
@@ -17,13 +17,21 @@ describe("LiteSVM", () => {
     const programPath = new URL("../target/deploy/amm.so", import.meta.url).pathname;
     svm.addProgramFromFile(programId, programPath);
 
+    // Make the payer the program's upgrade authority: ProgramData is a u32 tag, a u64 slot,
+    // then the optional authority
+    const [programData] = PublicKey.findProgramAddressSync([programId.toBuffer()], new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111"));
+    const programDataAccount = svm.getAccount(programData);
+    programDataAccount.data.set([1, ...payer.publicKey.toBuffer()], 12);
+    svm.setAccount(programData, programDataAccount);
+
     const initializer = Keypair.generate(); //pool initializer
     const authority = Keypair.generate(); // pool authority
     svm.airdrop(authority.publicKey, BigInt(3 * 10 ** 9));
     svm.airdrop(initializer.publicKey, BigInt(3 * 10 ** 9));
 
 
-    const usdcMint = new PublicKey("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    // Pools require mint_m to sort below mint_n; unique keys sort below the BONK address
+    const usdcMint = PublicKey.unique();
     const bonkMint = new PublicKey("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263");
     const poolSeed = new anchor.BN(1);
 
@@ -39,6 +47,90 @@ describe("LiteSVM", () => {
         configPda.toBuffer()
     ], programId);
 
+    // Observation buffer of pool1; once initialized, its reserve-changing instructions require it
+    const [oraclePda] = PublicKey.findProgramAddressSync([
+        Buffer.from("oracle"),
+        configPda.toBuffer()
+    ], programId);
+
+    // Instructions that emit events end with the event authority and the program itself
+    const [eventAuthority] = PublicKey.findProgramAddressSync([Buffer.from("__event_authority")], programId);
+    const eventCpiKeys = [
+        { pubkey: eventAuthority, isWritable: false, isSigner: false },
+        { pubkey: programId, isWritable: false, isSigner: false }
+    ];
+
+    // emit_cpi! prefixes each event with Anchor's event instruction tag
+    const eventIxTag = Buffer.from("e445a52e51cb9a1d", "hex").reverse();
+    const emittedEvents = (res: any) => res.innerInstructions().flat()
+        .map((inner: any) => Buffer.from(inner.instruction().data()))
+        .filter((data: Buffer) => data.subarray(0, 8).equals(eventIxTag))
+        .map((data: Buffer) => coder.events.decode(data.subarray(8).toString("base64")));
+
+    // Registry entry claimed by the one pool for an ordered mint pair and fee tier
+    const pairPda = (mintM: PublicKey, mintN: PublicKey, fee: anchor.BN) => PublicKey.findProgramAddressSync([
+        Buffer.from("pair"),
+        mintM.toBuffer(),
+        mintN.toBuffer(),
+        fee.toArrayLike(Buffer, "le", 2)
+    ], programId)[0];
+
+    // Program-wide config: allowed fee tiers, the default protocol fee share and the pause flag
+    const [protocolPda] = PublicKey.findProgramAddressSync([Buffer.from("protocol")], programId);
+    const protocolAdmin = Keypair.generate();
+    svm.airdrop(protocolAdmin.publicKey, BigInt(10 ** 9));
+
+    // Initializes a constant-product pool seeded with one unit of each token
+    const initializePool = (seed: anchor.BN, mintM: PublicKey, mintN: PublicKey, fee: anchor.BN) => {
+        const [config] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            seed.toArrayLike(Buffer, "le", 8),
+            mintM.toBuffer(),
+            mintN.toBuffer()
+        ], programId);
+        const [lp] = PublicKey.findProgramAddressSync([Buffer.from("liquiditypool"), config.toBuffer()], programId);
+
+        const tx = new Transaction().add(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: mintM, isWritable: false, isSigner: false },
+                { pubkey: mintN, isWritable: false, isSigner: false },
+                { pubkey: lp, isWritable: true, isSigner: false },
+                { pubkey: getAssociatedTokenAddressSync(mintM, config, true), isWritable: true, isSigner: false },
+                { pubkey: getAssociatedTokenAddressSync(mintN, config, true), isWritable: true, isSigner: false },
+                { pubkey: getAssociatedTokenAddressSync(mintM, initializer.publicKey, true), isWritable: true, isSigner: false },
+                { pubkey: getAssociatedTokenAddressSync(mintN, initializer.publicKey, true), isWritable: true, isSigner: false },
+                { pubkey: getAssociatedTokenAddressSync(lp, initializer.publicKey, true), isWritable: true, isSigner: false },
+                { pubkey: config, isWritable: true, isSigner: false },
+                { pubkey: pairPda(mintM, mintN, fee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("initialize", {
+                seed,
+                authority: authority.publicKey,
+                fee,
+                init_m_amount: new anchor.BN(10 ** 6),
+                init_n_amount: new anchor.BN(10 ** 6),
+                curve: { constantProduct: {} },
+                amp: new anchor.BN(0),
+                weight_m: 5000
+            })
+        }));
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        const res = svm.sendTransaction(tx);
+        svm.expireBlockhash();
+        return res;
+    };
+
 
     const vaultMAta = getAssociatedTokenAddressSync(usdcMint, configPda, true);
     const vaultNAta = getAssociatedTokenAddressSync(bonkMint, configPda, true);
@@ -181,14 +273,54 @@ describe("LiteSVM", () => {
         expect(initializerDecodedY.amount, "Taker's ATA should hold the correct initial USDC amount").to.eql(InitializerHaveBonk);
     });
 
+    before("Initialized protocol config", () => {
+        const initializeProtocol = (signer: Keypair) => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: signer.publicKey, isWritable: true, isSigner: true },
+                    { pubkey: protocolPda, isWritable: true, isSigner: false },
+                    { pubkey: programData, isWritable: false, isSigner: false },
+                    { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode("initialize_protocol", {
+                    admin: protocolAdmin.publicKey,
+                    fee_tiers: [1, 5, 30, 100],
+                    default_protocol_fee: 0,
+                    treasury: protocolAdmin.publicKey
+                })
+            }));
+            tx.feePayer = signer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(signer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        // The config is a singleton, so only the upgrade authority may claim it
+        expect(initializeProtocol(authority), "Anyone but the upgrade authority should be rejected")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(svm.getAccount(protocolPda), "A rejected caller should not create the config").to.be.null;
+        const res = initializeProtocol(payer);
+        expect(res instanceof FailedTransactionMetadata, "The upgrade authority should initialize").to.be.false;
+
+        const [event] = emittedEvents(res);
+        expect(event.name).to.equal("ProtocolUpdated");
+        expect(event.data.admin.toBase58(), "The event should name the admin").to.equal(protocolAdmin.publicKey.toBase58());
+    });
+
     it("Initialize pool", () => {
         const ixArgs = {
             seed: poolSeed,
             authority: authority.publicKey,
             fee: poolFee,
             init_m_amount: initialAmountM,
-            init_n_amount: initialAmountN
-
+            init_n_amount: initialAmountN,
+            curve: { constantProduct: {} },
+            amp: new anchor.BN(0),
+            weight_m: 5000
         };
 
         const data = coder.instruction.encode("initialize", ixArgs);
@@ -204,10 +336,14 @@ describe("LiteSVM", () => {
                 { pubkey: initializerNAta, isWritable: true, isSigner: false },
                 { pubkey: initializerLpAta, isWritable: true, isSigner: false },
                 { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: pairPda(usdcMint, bonkMint, poolFee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
                 { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
                 { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
-                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
-                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false }
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
             ],
             programId,
             data
@@ -232,6 +368,8 @@ describe("LiteSVM", () => {
             .to.equal(bonkMint.toBase58());
         expect(config.fee, "Config 'fee' should match input poolFee")
             .to.equal(Number(poolFee));
+        expect(config.treasury.toBase58(), "Config 'treasury' should be the protocol's treasury")
+            .to.equal(protocolAdmin.publicKey.toBase58());
         expect(config.bump_lp, "Config 'lp_bump' should be a number").to.be.a("number");
         expect(config.bump, "Config 'bump' should be a number").to.be.a("number");
         expect(config.locked, "Config 'locked' should be a boolean").to.be.a("boolean");
@@ -239,4 +377,2370 @@ describe("LiteSVM", () => {
         expect(config.bump_lp).to.be.a("number");
         expect(config.bump).to.be.a("number");
     })
-});
\ No newline at end of file
+
+    it("Swap USDC for BONK", () => {
+        const amountIn = new anchor.BN(100 * 10 ** 6);
+        const expiration = new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString());
+
+        const vaultMBefore = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNBefore = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const userNBefore = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount;
+
+        // x * y = k after a 0.30% fee on the input
+        const amountInAfterFee = BigInt(amountIn.toString()) * BigInt(10000 - Number(poolFee)) / BigInt(10000);
+        const expectedOut = vaultNBefore * amountInAfterFee / (vaultMBefore + amountInAfterFee);
+
+        const ixArgs = {
+            is_m: true,
+            amount_in: amountIn,
+            min_out: new anchor.BN(expectedOut.toString()),
+            expiration,
+        };
+
+        const data = coder.instruction.encode("swap", ixArgs);
+        const ix = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data
+        })
+
+        const tx = new Transaction().add(ix);
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        svm.sendTransaction(tx);
+
+        const vaultMAfter = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNAfter = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const userNAfter = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount;
+
+        expect(vaultMAfter, "Vault M should receive the full input amount")
+            .to.eql(vaultMBefore + BigInt(amountIn.toString()));
+        expect(vaultNAfter, "Vault N should pay out the quoted amount")
+            .to.eql(vaultNBefore - expectedOut);
+        expect(userNAfter, "User should receive the quoted BONK amount")
+            .to.eql(userNBefore + expectedOut);
+    })
+
+    it("Swap BONK for an exact amount of USDC", () => {
+        const amountOut = new anchor.BN(50 * 10 ** 6);
+        const expiration = new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString());
+
+        const vaultMBefore = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNBefore = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const userMBefore = AccountLayout.decode(svm.getAccount(initializerMAta).data).amount;
+
+        // Required input rounds up at both steps, in favour of the pool
+        const out = BigInt(amountOut.toString());
+        const ceilDiv = (a: bigint, b: bigint) => (a + b - BigInt(1)) / b;
+        const inAfterFee = ceilDiv(vaultNBefore * out, vaultMBefore - out);
+        const expectedIn = ceilDiv(inAfterFee * BigInt(10000), BigInt(10000 - Number(poolFee)));
+
+        const ixArgs = {
+            is_m: false,
+            amount_out: amountOut,
+            max_in: new anchor.BN(expectedIn.toString()),
+            expiration,
+        };
+
+        const data = coder.instruction.encode("swap_exact_out", ixArgs);
+        const ix = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data
+        })
+
+        const tx = new Transaction().add(ix);
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        svm.sendTransaction(tx);
+
+        const vaultMAfter = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNAfter = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const userMAfter = AccountLayout.decode(svm.getAccount(initializerMAta).data).amount;
+
+        expect(userMAfter, "User should receive exactly the requested USDC")
+            .to.eql(userMBefore + out);
+        expect(vaultMAfter, "Vault M should pay out the requested amount")
+            .to.eql(vaultMBefore - out);
+        expect(vaultNAfter, "Vault N should receive the quoted input")
+            .to.eql(vaultNBefore + expectedIn);
+    })
+
+    it("Deposit liquidity proportionally", () => {
+        const lpAmount = new anchor.BN(1_000_000);
+        const expiration = new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString());
+
+        const vaultMBefore = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNBefore = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const lpSupplyBefore = MintLayout.decode(svm.getAccount(lpMint).data).supply;
+        const userLpBefore = AccountLayout.decode(svm.getAccount(initializerLpAta).data).amount;
+
+        // The never-minted MINIMUM_LIQUIDITY still counts towards the total LP backing the reserves
+        const lp = BigInt(lpAmount.toString());
+        const totalLp = lpSupplyBefore + BigInt(1_000);
+        const ceilDiv = (a: bigint, b: bigint) => (a + b - BigInt(1)) / b;
+        const expectedM = ceilDiv(vaultMBefore * lp, totalLp);
+        const expectedN = ceilDiv(vaultNBefore * lp, totalLp);
+
+        const ixArgs = {
+            amount: lpAmount,
+            max_m: new anchor.BN(expectedM.toString()),
+            max_n: new anchor.BN(expectedN.toString()),
+            expiration,
+        };
+
+        const data = coder.instruction.encode("deposit", ixArgs);
+        const ix = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: lpMint, isWritable: true, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data
+        })
+
+        const tx = new Transaction().add(ix);
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        svm.sendTransaction(tx);
+
+        const vaultMAfter = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNAfter = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const userLpAfter = AccountLayout.decode(svm.getAccount(initializerLpAta).data).amount;
+
+        expect(vaultMAfter, "Vault M should receive its proportional share")
+            .to.eql(vaultMBefore + expectedM);
+        expect(vaultNAfter, "Vault N should receive its proportional share")
+            .to.eql(vaultNBefore + expectedN);
+        expect(userLpAfter, "User should be minted the requested LP amount")
+            .to.eql(userLpBefore + lp);
+    })
+
+    it("Withdraw liquidity by burning LP", () => {
+        const lpAmount = new anchor.BN(1_000_000);
+        const expiration = new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString());
+
+        const vaultMBefore = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNBefore = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const lpSupplyBefore = MintLayout.decode(svm.getAccount(lpMint).data).supply;
+        const userLpBefore = AccountLayout.decode(svm.getAccount(initializerLpAta).data).amount;
+
+        // Withdrawals round down, in favour of the remaining LPs
+        const lp = BigInt(lpAmount.toString());
+        const totalLp = lpSupplyBefore + BigInt(1_000);
+        const expectedM = vaultMBefore * lp / totalLp;
+        const expectedN = vaultNBefore * lp / totalLp;
+
+        const ixArgs = {
+            amount: lpAmount,
+            min_m: new anchor.BN(expectedM.toString()),
+            min_n: new anchor.BN(expectedN.toString()),
+            expiration,
+        };
+
+        const data = coder.instruction.encode("withdraw", ixArgs);
+        const ix = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: lpMint, isWritable: true, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data
+        })
+
+        const tx = new Transaction().add(ix);
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        svm.sendTransaction(tx);
+
+        const vaultMAfter = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNAfter = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        const userLpAfter = AccountLayout.decode(svm.getAccount(initializerLpAta).data).amount;
+
+        expect(vaultMAfter, "Vault M should pay out its pro-rata share")
+            .to.eql(vaultMBefore - expectedM);
+        expect(vaultNAfter, "Vault N should pay out its pro-rata share")
+            .to.eql(vaultNBefore - expectedN);
+        expect(userLpAfter, "Burned LP should leave the user's LP account")
+            .to.eql(userLpBefore - lp);
+    })
+
+    it("Lock and unlock pool by authority", () => {
+        const sendAdminIx = (name: string) => {
+            const data = coder.instruction.encode(name, {});
+            const ix = new TransactionInstruction({
+                keys: [
+                    { pubkey: authority.publicKey, isWritable: false, isSigner: true },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data
+            })
+
+            const tx = new Transaction().add(ix);
+            tx.feePayer = authority.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(authority);
+            svm.sendTransaction(tx);
+            svm.expireBlockhash();
+
+            return coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        };
+
+        expect(sendAdminIx("lock").locked, "Config 'locked' should be true after lock").to.be.true;
+
+        // Locking stops trading, but LPs can always withdraw
+        const withdrawTx = new Transaction().add(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: lpMint, isWritable: true, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("withdraw", {
+                amount: new anchor.BN(1_000),
+                min_m: new anchor.BN(0),
+                min_n: new anchor.BN(0),
+                expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+            })
+        }));
+        withdrawTx.feePayer = initializer.publicKey;
+        withdrawTx.recentBlockhash = svm.latestBlockhash();
+        withdrawTx.sign(initializer);
+        expect(svm.sendTransaction(withdrawTx) instanceof FailedTransactionMetadata, "Withdrawals should stay open while locked")
+            .to.be.false;
+        svm.expireBlockhash();
+
+        expect(sendAdminIx("unlock").locked, "Config 'locked' should be false after unlock").to.be.false;
+    })
+
+    it("Queue a fee update and apply it after the timelock", () => {
+        const newFee = 5; // one of the protocol's fee tiers
+        const delay = BigInt(86_400);
+        const effectiveAt = svm.getClock().unixTimestamp + delay;
+
+        const sendUpdate = (fee: number) => {
+            const updateIx = new TransactionInstruction({
+                keys: [
+                    { pubkey: authority.publicKey, isWritable: false, isSigner: true },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode("update_fee", { fee, effective_at: new anchor.BN(effectiveAt.toString()) })
+            })
+
+            const updateTx = new Transaction().add(updateIx);
+            updateTx.feePayer = authority.publicKey;
+            updateTx.recentBlockhash = svm.latestBlockhash();
+            updateTx.sign(authority);
+            const res = svm.sendTransaction(updateTx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        expect(sendUpdate(25), "A fee outside the protocol's tiers should be rejected")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(sendUpdate(newFee) instanceof FailedTransactionMetadata, "A tier fee should be queued").to.be.false;
+
+        const queued = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(queued.pending_fee, "Config 'pending_fee' should hold the queued fee").to.equal(newFee);
+        expect(queued.fee, "Config 'fee' should not change before the timelock").to.equal(Number(poolFee));
+
+        const sendApply = () => {
+            const applyIx = new TransactionInstruction({
+                keys: [{ pubkey: configPda, isWritable: true, isSigner: false }, ...eventCpiKeys],
+                programId,
+                data: coder.instruction.encode("apply_fee", {})
+            })
+
+            const applyTx = new Transaction().add(applyIx);
+            applyTx.feePayer = payer.publicKey;
+            applyTx.recentBlockhash = svm.latestBlockhash();
+            applyTx.sign(payer);
+            const res = svm.sendTransaction(applyTx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        expect(sendApply(), "apply_fee should fail before the timelock expires")
+            .to.be.instanceOf(FailedTransactionMetadata);
+
+        const clock = svm.getClock();
+        clock.unixTimestamp = effectiveAt;
+        svm.setClock(clock);
+        sendApply();
+
+        const applied = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(applied.fee, "Config 'fee' should be the queued fee after apply").to.equal(newFee);
+        expect(applied.pending_fee, "Config 'pending_fee' should be cleared after apply").to.be.null;
+    })
+
+    it("Accrue protocol fees on swaps and claim them to the treasury", () => {
+        const protocolFee = 5000; // half of the swap fee
+        const effectiveAt = svm.getClock().unixTimestamp + BigInt(86_400);
+        const treasuryMAta = getAssociatedTokenAddressSync(usdcMint, authority.publicKey, true);
+        const treasuryNAta = getAssociatedTokenAddressSync(bonkMint, authority.publicKey, true);
+
+        // The protocol's share is set by the protocol admin, not the pool authority
+        const setIx = (signer: Keypair) => new TransactionInstruction({
+            keys: [
+                { pubkey: signer.publicKey, isWritable: false, isSigner: true },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("set_protocol_fee", {
+                protocol_fee: protocolFee,
+                treasury: authority.publicKey,
+                effective_at: new anchor.BN(effectiveAt.toString())
+            })
+        })
+        const sendSet = (signer: Keypair) => {
+            const tx = new Transaction().add(setIx(signer));
+            tx.feePayer = payer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(payer, signer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+        const applyIx = new TransactionInstruction({
+            keys: [{ pubkey: configPda, isWritable: true, isSigner: false }, ...eventCpiKeys],
+            programId,
+            data: coder.instruction.encode("apply_fee", {})
+        })
+
+        expect(sendSet(authority), "The pool authority should not set the protocol fee").to.be.instanceOf(FailedTransactionMetadata);
+
+        // The new split is queued behind the same timelock as swap fee changes
+        expect(sendSet(protocolAdmin) instanceof FailedTransactionMetadata, "The protocol admin should set the protocol fee").to.be.false;
+        const queued = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(queued.protocol_fee, "Config 'protocol_fee' should not change before the timelock").to.equal(0);
+        expect(queued.pending_protocol_fee, "Config 'pending_protocol_fee' should hold the queued share").to.equal(protocolFee);
+
+        const clock = svm.getClock();
+        clock.unixTimestamp = effectiveAt;
+        svm.setClock(clock);
+        const applyTx = new Transaction().add(applyIx);
+        applyTx.feePayer = payer.publicKey;
+        applyTx.recentBlockhash = svm.latestBlockhash();
+        applyTx.sign(payer);
+        svm.sendTransaction(applyTx);
+        svm.expireBlockhash();
+
+        const amountIn = BigInt(200 * 10 ** 6);
+        const swapIx = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: true,
+                amount_in: new anchor.BN(amountIn.toString()),
+                min_out: new anchor.BN(0),
+                expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+            })
+        })
+
+        const tx = new Transaction().add(swapIx);
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        svm.sendTransaction(tx);
+
+        const fee = BigInt(coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data)).fee);
+        const swapFee = amountIn - amountIn * (BigInt(10000) - fee) / BigInt(10000);
+        const expectedProtocolFee = swapFee * BigInt(protocolFee) / BigInt(10000);
+
+        const accrued = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(BigInt(accrued.protocol_fees_m.toString()), "Config 'protocol_fees_m' should hold the treasury's cut")
+            .to.eql(expectedProtocolFee);
+
+        const claimIx = new TransactionInstruction({
+            keys: [
+                { pubkey: payer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: authority.publicKey, isWritable: false, isSigner: false },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: treasuryMAta, isWritable: true, isSigner: false },
+                { pubkey: treasuryNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("claim_protocol_fees", {})
+        })
+
+        const claimTx = new Transaction().add(claimIx);
+        claimTx.feePayer = payer.publicKey;
+        claimTx.recentBlockhash = svm.latestBlockhash();
+        claimTx.sign(payer);
+        svm.sendTransaction(claimTx);
+
+        const treasuryM = AccountLayout.decode(svm.getAccount(treasuryMAta).data).amount;
+        const claimed = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(treasuryM, "Treasury should receive the accrued USDC fees").to.eql(expectedProtocolFee);
+        expect(claimed.protocol_fees_m.toString(), "Config 'protocol_fees_m' should reset after claim").to.equal("0");
+    })
+
+    it("Lock LP in a vesting position, withdraw once unlocked and close it", () => {
+        const positionSeed = new anchor.BN(1);
+        const lockAmount = new anchor.BN(500_000);
+        const now = svm.getClock().unixTimestamp;
+
+        const [positionPda] = PublicKey.findProgramAddressSync([
+            Buffer.from("position"),
+            configPda.toBuffer(),
+            initializer.publicKey.toBuffer(),
+            positionSeed.toArrayLike(Buffer, "le", 8)
+        ], programId);
+        const positionLpAta = getAssociatedTokenAddressSync(lpMint, positionPda, true);
+
+        const send = (ix: TransactionInstruction) => {
+            const tx = new Transaction().add(ix);
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: configPda, isWritable: false, isSigner: false },
+                { pubkey: lpMint, isWritable: false, isSigner: false },
+                { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                { pubkey: positionPda, isWritable: true, isSigner: false },
+                { pubkey: positionLpAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false }
+            ],
+            programId,
+            data: coder.instruction.encode("create_position", {
+                seed: positionSeed,
+                amount: lockAmount,
+                start_ts: new anchor.BN(now.toString()),
+                cliff_ts: new anchor.BN((now + BigInt(100)).toString()),
+                end_ts: new anchor.BN((now + BigInt(1000)).toString()),
+            })
+        }));
+
+        const position = coder.accounts.decode("Position", Buffer.from(svm.getAccount(positionPda).data));
+        expect(position.owner.toBase58(), "Position 'owner' should be the creator").to.equal(initializer.publicKey.toBase58());
+        expect(position.total_amount.toString(), "Position should hold the locked LP").to.equal(lockAmount.toString());
+
+        const withdrawIx = (amount: anchor.BN) => new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: configPda, isWritable: false, isSigner: false },
+                { pubkey: lpMint, isWritable: false, isSigner: false },
+                { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                { pubkey: positionPda, isWritable: true, isSigner: false },
+                { pubkey: positionLpAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false }
+            ],
+            programId,
+            data: coder.instruction.encode("withdraw_position", { amount })
+        });
+
+        expect(send(withdrawIx(new anchor.BN(1))), "Nothing should unlock before the cliff")
+            .to.be.instanceOf(FailedTransactionMetadata);
+
+        const clock = svm.getClock();
+        clock.unixTimestamp = now + BigInt(1000);
+        svm.setClock(clock);
+        send(withdrawIx(lockAmount));
+
+        // LP sent to the emptied position must not keep it from closing; it goes to the owner
+        const lpBalance = () => AccountLayout.decode(svm.getAccount(initializerLpAta).data).amount;
+        send(createTransferInstruction(initializerLpAta, positionLpAta, initializer.publicKey, 1));
+        const lpBeforeClose = lpBalance();
+
+        const closeRes = send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: configPda, isWritable: false, isSigner: false },
+                { pubkey: lpMint, isWritable: false, isSigner: false },
+                { pubkey: positionPda, isWritable: true, isSigner: false },
+                { pubkey: positionLpAta, isWritable: true, isSigner: false },
+                { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false }
+            ],
+            programId,
+            data: coder.instruction.encode("close_position", {})
+        }));
+
+        expect(closeRes instanceof FailedTransactionMetadata, "Close should succeed despite the stray LP").to.be.false;
+        expect(lpBalance() - lpBeforeClose, "The stray LP should be swept to the owner").to.eql(BigInt(1));
+        expect(svm.getAccount(positionPda), "Position should be closed").to.be.null;
+        expect(svm.getAccount(positionLpAta), "Position LP account should be closed").to.be.null;
+    })
+
+    it("Initialize pool with a Token-2022 mint and Token-2022 LP", () => {
+        const t22Mint = PublicKey.unique();
+        const t22Seed = new anchor.BN(2);
+        const t22Amount = BigInt(10_000 * 10 ** 6);
+
+        const mintData = Buffer.alloc(MINT_SIZE);
+        MintLayout.encode(
+            {
+                mintAuthorityOption: 1,
+                mintAuthority: PublicKey.unique(),
+                supply: t22Amount,
+                decimals: 6,
+                isInitialized: true,
+                freezeAuthorityOption: 0,
+                freezeAuthority: PublicKey.default,
+            },
+            mintData
+        );
+        svm.setAccount(t22Mint, {
+            lamports: 1_000_000_000,
+            data: mintData,
+            owner: TOKEN_2022_PROGRAM_ID,
+            executable: false,
+        });
+
+        const initializerT22Ata = getAssociatedTokenAddressSync(t22Mint, initializer.publicKey, true, TOKEN_2022_PROGRAM_ID);
+        const ataData = Buffer.alloc(ACCOUNT_SIZE);
+        AccountLayout.encode(
+            {
+                mint: t22Mint,
+                owner: initializer.publicKey,
+                amount: t22Amount,
+                delegateOption: 0,
+                delegate: PublicKey.default,
+                delegatedAmount: BigInt(0),
+                state: 1,
+                isNativeOption: 0,
+                isNative: BigInt(0),
+                closeAuthorityOption: 0,
+                closeAuthority: PublicKey.default,
+            },
+            ataData,
+        );
+        svm.setAccount(initializerT22Ata, {
+            lamports: 1_000_000_000,
+            data: ataData,
+            owner: TOKEN_2022_PROGRAM_ID,
+            executable: false,
+        });
+
+        const [t22Config] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            t22Seed.toArrayLike(Buffer, "le", 8),
+            t22Mint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const [t22LpMint] = PublicKey.findProgramAddressSync([
+            Buffer.from("liquiditypool"),
+            t22Config.toBuffer()
+        ], programId);
+        const t22VaultM = getAssociatedTokenAddressSync(t22Mint, t22Config, true, TOKEN_2022_PROGRAM_ID);
+        const t22VaultN = getAssociatedTokenAddressSync(bonkMint, t22Config, true);
+        const t22InitializerLp = getAssociatedTokenAddressSync(t22LpMint, initializer.publicKey, true, TOKEN_2022_PROGRAM_ID);
+
+        const data = coder.instruction.encode("initialize", {
+            seed: t22Seed,
+            authority: authority.publicKey,
+            fee: poolFee,
+            init_m_amount: initialAmountM,
+            init_n_amount: initialAmountN,
+            curve: { constantProduct: {} },
+            amp: new anchor.BN(0),
+            weight_m: 5000
+        });
+        const ix = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: t22Mint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: t22LpMint, isWritable: true, isSigner: false },
+                { pubkey: t22VaultM, isWritable: true, isSigner: false },
+                { pubkey: t22VaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerT22Ata, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: t22InitializerLp, isWritable: true, isSigner: false },
+                { pubkey: t22Config, isWritable: true, isSigner: false },
+                { pubkey: pairPda(t22Mint, bonkMint, poolFee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data
+        })
+
+        const tx = new Transaction().add(ix);
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        svm.sendTransaction(tx);
+
+        expect(svm.getAccount(t22VaultM).owner.toBase58(), "Vault M should be a Token-2022 account")
+            .to.equal(TOKEN_2022_PROGRAM_ID.toBase58());
+        expect(svm.getAccount(t22LpMint).owner.toBase58(), "LP mint should be created under Token-2022")
+            .to.equal(TOKEN_2022_PROGRAM_ID.toBase58());
+        expect(AccountLayout.decode(svm.getAccount(t22VaultM).data).amount, "Vault M should hold the initial deposit")
+            .to.eql(BigInt(initialAmountM.toString()));
+    })
+
+    it("Price pools on the net amounts of a Token-2022 transfer-fee mint", () => {
+        const feeMint = PublicKey.unique();
+        const feeSeed = new anchor.BN(10);
+        const feeMintAmount = BigInt(100_000 * 10 ** 6);
+        const transferFeeBps = 100; // 1% of every transfer of the fee mint is withheld
+        const transferFee = (amount: bigint) => (amount * BigInt(transferFeeBps) + BigInt(9_999)) / BigInt(10_000);
+        const isqrt = (n: bigint) => {
+            let x = n, y = (x + BigInt(1)) / BigInt(2);
+            while (y < x) { x = y; y = (x + n / x) / BigInt(2); }
+            return x;
+        };
+
+        // Token-2022 lays extensions out after the base state padded to a token account's size:
+        // an account type byte, then each extension as type, length and data
+        const mintData = Buffer.alloc(ACCOUNT_SIZE + 1 + 4 + 108);
+        MintLayout.encode(
+            {
+                mintAuthorityOption: 1,
+                mintAuthority: PublicKey.unique(),
+                supply: feeMintAmount,
+                decimals: 6,
+                isInitialized: true,
+                freezeAuthorityOption: 0,
+                freezeAuthority: PublicKey.default,
+            },
+            mintData
+        );
+        mintData.writeUInt8(1, ACCOUNT_SIZE); // AccountType::Mint
+        mintData.writeUInt16LE(1, ACCOUNT_SIZE + 1); // ExtensionType::TransferFeeConfig
+        mintData.writeUInt16LE(108, ACCOUNT_SIZE + 3);
+        // Both authorities and the withheld amount stay zeroed; the older and newer fees follow them
+        for (const offset of [ACCOUNT_SIZE + 5 + 72, ACCOUNT_SIZE + 5 + 90]) {
+            mintData.writeBigUInt64LE(BigInt(0), offset); // epoch
+            mintData.writeBigUInt64LE(BigInt("18446744073709551615"), offset + 8); // maximum fee
+            mintData.writeUInt16LE(transferFeeBps, offset + 16);
+        }
+        svm.setAccount(feeMint, {
+            lamports: 1_000_000_000,
+            data: mintData,
+            owner: TOKEN_2022_PROGRAM_ID,
+            executable: false,
+        });
+
+        // Token accounts of a transfer-fee mint carry a TransferFeeAmount extension for withheld fees
+        const initializerFeeAta = getAssociatedTokenAddressSync(feeMint, initializer.publicKey, true, TOKEN_2022_PROGRAM_ID);
+        const ataData = Buffer.alloc(ACCOUNT_SIZE + 1 + 4 + 8);
+        AccountLayout.encode(
+            {
+                mint: feeMint,
+                owner: initializer.publicKey,
+                amount: feeMintAmount,
+                delegateOption: 0,
+                delegate: PublicKey.default,
+                delegatedAmount: BigInt(0),
+                state: 1,
+                isNativeOption: 0,
+                isNative: BigInt(0),
+                closeAuthorityOption: 0,
+                closeAuthority: PublicKey.default,
+            },
+            ataData,
+        );
+        ataData.writeUInt8(2, ACCOUNT_SIZE); // AccountType::Account
+        ataData.writeUInt16LE(2, ACCOUNT_SIZE + 1); // ExtensionType::TransferFeeAmount
+        ataData.writeUInt16LE(8, ACCOUNT_SIZE + 3);
+        svm.setAccount(initializerFeeAta, {
+            lamports: 1_000_000_000,
+            data: ataData,
+            owner: TOKEN_2022_PROGRAM_ID,
+            executable: false,
+        });
+
+        const [feeConfig] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            feeSeed.toArrayLike(Buffer, "le", 8),
+            feeMint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const [feeLpMint] = PublicKey.findProgramAddressSync([Buffer.from("liquiditypool"), feeConfig.toBuffer()], programId);
+        const feeVaultM = getAssociatedTokenAddressSync(feeMint, feeConfig, true, TOKEN_2022_PROGRAM_ID);
+        const feeVaultN = getAssociatedTokenAddressSync(bonkMint, feeConfig, true);
+        const feeInitializerLp = getAssociatedTokenAddressSync(feeLpMint, initializer.publicKey, true);
+        const balance = (account: PublicKey) => AccountLayout.decode(svm.getAccount(account).data).amount;
+        const send = (ix: TransactionInstruction) => {
+            const tx = new Transaction().add(ix);
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            expect(res instanceof FailedTransactionMetadata, "Transaction should succeed").to.be.false;
+        };
+        const swapKeys = [
+            { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+            { pubkey: feeMint, isWritable: false, isSigner: false },
+            { pubkey: bonkMint, isWritable: false, isSigner: false },
+            { pubkey: feeConfig, isWritable: true, isSigner: false },
+            { pubkey: protocolPda, isWritable: false, isSigner: false },
+            { pubkey: feeVaultM, isWritable: true, isSigner: false },
+            { pubkey: feeVaultN, isWritable: true, isSigner: false },
+            { pubkey: initializerFeeAta, isWritable: true, isSigner: false },
+            { pubkey: initializerNAta, isWritable: true, isSigner: false },
+            { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+            { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+            ...eventCpiKeys
+        ];
+        const expiration = () => new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString());
+
+        // Initialize: LP is priced on the M that reached the vault, not on what was sent
+        const initM = BigInt(1_000 * 10 ** 6);
+        const initN = BigInt(2_000 * 10 ** 6);
+        send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: feeMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: feeLpMint, isWritable: true, isSigner: false },
+                { pubkey: feeVaultM, isWritable: true, isSigner: false },
+                { pubkey: feeVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerFeeAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: feeInitializerLp, isWritable: true, isSigner: false },
+                { pubkey: feeConfig, isWritable: true, isSigner: false },
+                { pubkey: pairPda(feeMint, bonkMint, poolFee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("initialize", {
+                seed: feeSeed,
+                authority: authority.publicKey,
+                fee: poolFee,
+                init_m_amount: new anchor.BN(initM.toString()),
+                init_n_amount: new anchor.BN(initN.toString()),
+                curve: { constantProduct: {} },
+                amp: new anchor.BN(0),
+                weight_m: 5000
+            })
+        }));
+        const initNetM = initM - transferFee(initM);
+        expect(balance(feeVaultM), "Vault M should hold the initial deposit less the transfer fee").to.eql(initNetM);
+        expect(balance(feeInitializerLp), "LP should be priced on the net initial deposit")
+            .to.eql(isqrt(initNetM * initN) - BigInt(1_000));
+
+        // Deposit: the user is charged enough that the proportional amounts land in the vaults
+        const lpAmount = BigInt(1_000_000);
+        let vaultM = balance(feeVaultM);
+        let vaultN = balance(feeVaultN);
+        const totalLp = MintLayout.decode(svm.getAccount(feeLpMint).data).supply + BigInt(1_000);
+        const depositM = (vaultM * lpAmount + totalLp - BigInt(1)) / totalLp;
+        const depositN = (vaultN * lpAmount + totalLp - BigInt(1)) / totalLp;
+        const lpBefore = balance(feeInitializerLp);
+        send(new TransactionInstruction({
+            keys: [
+                ...swapKeys.slice(0, 5),
+                { pubkey: feeLpMint, isWritable: true, isSigner: false },
+                ...swapKeys.slice(5, 9),
+                { pubkey: feeInitializerLp, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...swapKeys.slice(12)
+            ],
+            programId,
+            data: coder.instruction.encode("deposit", {
+                amount: new anchor.BN(lpAmount.toString()),
+                max_m: new anchor.BN((depositM * BigInt(2)).toString()),
+                max_n: new anchor.BN(depositN.toString()),
+                expiration: expiration(),
+            })
+        }));
+        expect(balance(feeVaultM) - vaultM, "Vault M should receive exactly its proportional share").to.eql(depositM);
+        expect(balance(feeVaultN) - vaultN, "Vault N should receive exactly its proportional share").to.eql(depositN);
+        expect(balance(feeInitializerLp) - lpBefore, "The requested LP should be minted").to.eql(lpAmount);
+
+        // Exact-in swap: the output is quoted on the input that reached the vault
+        const amountIn = BigInt(10 * 10 ** 6);
+        vaultM = balance(feeVaultM);
+        vaultN = balance(feeVaultN);
+        const bonkBefore = balance(initializerNAta);
+        send(new TransactionInstruction({
+            keys: swapKeys,
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: true,
+                amount_in: new anchor.BN(amountIn.toString()),
+                min_out: new anchor.BN(1),
+                expiration: expiration(),
+            })
+        }));
+        const amountInNet = amountIn - transferFee(amountIn);
+        const afterFee = amountInNet * (BigInt(10_000) - BigInt(poolFee.toString())) / BigInt(10_000);
+        expect(balance(feeVaultM) - vaultM, "Vault M should receive the input less the transfer fee").to.eql(amountInNet);
+        expect(balance(initializerNAta) - bonkBefore, "Output should be priced on the net input")
+            .to.eql(vaultN * afterFee / (vaultM + afterFee));
+
+        // Exact-out swap: the vault sends enough that the user receives exactly amount_out
+        const amountOut = BigInt(5 * 10 ** 6);
+        vaultM = balance(feeVaultM);
+        const feeMintBefore = balance(initializerFeeAta);
+        send(new TransactionInstruction({
+            keys: swapKeys,
+            programId,
+            data: coder.instruction.encode("swap_exact_out", {
+                is_m: false,
+                amount_out: new anchor.BN(amountOut.toString()),
+                max_in: new anchor.BN(10 ** 10),
+                expiration: expiration(),
+            })
+        }));
+        const sent = vaultM - balance(feeVaultM);
+        expect(balance(initializerFeeAta) - feeMintBefore, "User should receive exactly amount_out").to.eql(amountOut);
+        expect(sent - transferFee(sent), "Vault M should send amount_out grossed up for the transfer fee").to.eql(amountOut);
+    })
+
+    it("Forward transfer-hook extra accounts on initialize and swap", () => {
+        const hookProgramId = new PublicKey("8CvDus6fMsy2XDuoeRTp5XjNx3J5ozJbMxrQp8Zs6iFn");
+        svm.addProgramFromFile(hookProgramId, new URL("../target/deploy/transfer_hook_fixture.so", import.meta.url).pathname);
+        const hookMint = PublicKey.unique();
+        const hookSeed = new anchor.BN(11);
+        const hookMintAmount = BigInt(100_000 * 10 ** 6);
+
+        // A TransferHook extension names the program Token-2022 invokes on every transfer of the mint
+        const mintData = Buffer.alloc(ACCOUNT_SIZE + 1 + 4 + 64);
+        MintLayout.encode(
+            {
+                mintAuthorityOption: 1,
+                mintAuthority: PublicKey.unique(),
+                supply: hookMintAmount,
+                decimals: 6,
+                isInitialized: true,
+                freezeAuthorityOption: 0,
+                freezeAuthority: PublicKey.default,
+            },
+            mintData
+        );
+        mintData.writeUInt8(1, ACCOUNT_SIZE); // AccountType::Mint
+        mintData.writeUInt16LE(14, ACCOUNT_SIZE + 1); // ExtensionType::TransferHook
+        mintData.writeUInt16LE(64, ACCOUNT_SIZE + 3);
+        hookProgramId.toBuffer().copy(mintData, ACCOUNT_SIZE + 5 + 32); // after the zeroed authority
+        svm.setAccount(hookMint, {
+            lamports: 1_000_000_000,
+            data: mintData,
+            owner: TOKEN_2022_PROGRAM_ID,
+            executable: false,
+        });
+
+        // Token accounts of a hooked mint carry a TransferHookAccount extension Token-2022 flags mid-transfer
+        const initializerHookAta = getAssociatedTokenAddressSync(hookMint, initializer.publicKey, true, TOKEN_2022_PROGRAM_ID);
+        const ataData = Buffer.alloc(ACCOUNT_SIZE + 1 + 4 + 1);
+        AccountLayout.encode(
+            {
+                mint: hookMint,
+                owner: initializer.publicKey,
+                amount: hookMintAmount,
+                delegateOption: 0,
+                delegate: PublicKey.default,
+                delegatedAmount: BigInt(0),
+                state: 1,
+                isNativeOption: 0,
+                isNative: BigInt(0),
+                closeAuthorityOption: 0,
+                closeAuthority: PublicKey.default,
+            },
+            ataData,
+        );
+        ataData.writeUInt8(2, ACCOUNT_SIZE); // AccountType::Account
+        ataData.writeUInt16LE(15, ACCOUNT_SIZE + 1); // ExtensionType::TransferHookAccount
+        ataData.writeUInt16LE(1, ACCOUNT_SIZE + 3);
+        svm.setAccount(initializerHookAta, {
+            lamports: 1_000_000_000,
+            data: ataData,
+            owner: TOKEN_2022_PROGRAM_ID,
+            executable: false,
+        });
+
+        const [hookConfig] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            hookSeed.toArrayLike(Buffer, "le", 8),
+            hookMint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const [hookLpMint] = PublicKey.findProgramAddressSync([Buffer.from("liquiditypool"), hookConfig.toBuffer()], programId);
+        const hookVaultM = getAssociatedTokenAddressSync(hookMint, hookConfig, true, TOKEN_2022_PROGRAM_ID);
+        const hookVaultN = getAssociatedTokenAddressSync(bonkMint, hookConfig, true);
+        const [extraAccountMetaList] = PublicKey.findProgramAddressSync([Buffer.from("extra-account-metas"), hookMint.toBuffer()], hookProgramId);
+        const [counter] = PublicKey.findProgramAddressSync([Buffer.from("counter")], hookProgramId);
+        const transfers = () => Buffer.from(svm.getAccount(counter).data).readBigUInt64LE(1);
+        const balance = (account: PublicKey) => AccountLayout.decode(svm.getAccount(account).data).amount;
+        const send = (ix: TransactionInstruction) => {
+            const tx = new Transaction().add(ix);
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        // The hook program's validation account lists one extra account: its transfer counter
+        let res = send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: extraAccountMetaList, isWritable: true, isSigner: false },
+                { pubkey: hookMint, isWritable: false, isSigner: false },
+                { pubkey: counter, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+            ],
+            programId: hookProgramId,
+            data: Buffer.from([0])
+        }));
+        expect(res instanceof FailedTransactionMetadata, "Hook validation account should be initialized").to.be.false;
+
+        // Token-2022 resolves the hook's accounts from whatever the pool forwards out of remaining_accounts
+        const hookKeys = [
+            { pubkey: counter, isWritable: true, isSigner: false },
+            { pubkey: hookProgramId, isWritable: false, isSigner: false },
+            { pubkey: extraAccountMetaList, isWritable: false, isSigner: false },
+        ];
+
+        const initM = BigInt(1_000 * 10 ** 6);
+        const initN = BigInt(2_000 * 10 ** 6);
+        res = send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: hookMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: hookLpMint, isWritable: true, isSigner: false },
+                { pubkey: hookVaultM, isWritable: true, isSigner: false },
+                { pubkey: hookVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerHookAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: getAssociatedTokenAddressSync(hookLpMint, initializer.publicKey, true), isWritable: true, isSigner: false },
+                { pubkey: hookConfig, isWritable: true, isSigner: false },
+                { pubkey: pairPda(hookMint, bonkMint, poolFee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys,
+                ...hookKeys
+            ],
+            programId,
+            data: coder.instruction.encode("initialize", {
+                seed: hookSeed,
+                authority: authority.publicKey,
+                fee: poolFee,
+                init_m_amount: new anchor.BN(initM.toString()),
+                init_n_amount: new anchor.BN(initN.toString()),
+                curve: { constantProduct: {} },
+                amp: new anchor.BN(0),
+                weight_m: 5000
+            })
+        }));
+        expect(res instanceof FailedTransactionMetadata, "Initialize should forward the hook accounts").to.be.false;
+        expect(balance(hookVaultM), "Vault M should hold the initial deposit").to.eql(initM);
+        expect(transfers(), "The hook should run on the initial deposit").to.eql(BigInt(1));
+
+        const swap = (isM: boolean, extraKeys: typeof hookKeys) => send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: hookMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: hookConfig, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: hookVaultM, isWritable: true, isSigner: false },
+                { pubkey: hookVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerHookAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys,
+                ...extraKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: isM,
+                amount_in: new anchor.BN(10 ** 7),
+                min_out: new anchor.BN(1),
+                expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+            })
+        }));
+
+        // Token-2022 refuses a hooked transfer whose extra accounts are missing
+        expect(swap(true, []) instanceof FailedTransactionMetadata, "Swap without the hook accounts should fail").to.be.true;
+
+        // Both directions move the hooked mint once: in from the user, then out of the vault
+        const vaultM = balance(hookVaultM);
+        expect(swap(true, hookKeys) instanceof FailedTransactionMetadata, "Swap in should forward the hook accounts").to.be.false;
+        expect(balance(hookVaultM) - vaultM, "Vault M should receive the full input").to.eql(BigInt(10 ** 7));
+        expect(swap(false, hookKeys) instanceof FailedTransactionMetadata, "Swap out should forward the hook accounts").to.be.false;
+        expect(transfers(), "The hook should run once per swap").to.eql(BigInt(3));
+
+        // A route hop through the hooked pool carries the hook's accounts right after its own
+        const hookBefore = balance(initializerHookAta);
+        res = send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                ...eventCpiKeys,
+                { pubkey: hookConfig, isWritable: true, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: hookMint, isWritable: false, isSigner: false },
+                { pubkey: hookVaultN, isWritable: true, isSigner: false },
+                { pubkey: hookVaultM, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerHookAta, isWritable: true, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_2022_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...hookKeys
+            ],
+            programId,
+            data: coder.instruction.encode("route_swap", {
+                amount_in: new anchor.BN(10 ** 7),
+                min_out: new anchor.BN(1),
+                expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+                hook_accounts: Buffer.from([hookKeys.length]),
+            })
+        }));
+        expect(res instanceof FailedTransactionMetadata, "Route should forward the hop's hook accounts").to.be.false;
+        expect(balance(initializerHookAta) > hookBefore, "Route should pay out the hooked mint").to.be.true;
+        expect(transfers(), "The hook should run on the routed transfer").to.eql(BigInt(4));
+    })
+
+    it("Accumulate TWAP prices on reserve-changing instructions", () => {
+        const before = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        const reserveM = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount - BigInt(before.protocol_fees_m.toString());
+        const reserveN = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount - BigInt(before.protocol_fees_n.toString());
+
+        const elapsed = BigInt(600);
+        const clock = svm.getClock();
+        clock.unixTimestamp = BigInt(before.last_update_ts.toString()) + elapsed;
+        svm.setClock(clock);
+
+        const ix = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: true,
+                amount_in: new anchor.BN(1_000_000),
+                min_out: new anchor.BN(0),
+                expiration: new anchor.BN((clock.unixTimestamp + BigInt(60)).toString()),
+            })
+        })
+
+        const tx = new Transaction().add(ix);
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        svm.sendTransaction(tx);
+
+        // Q64.64 spot prices held over the elapsed window
+        const priceM = (reserveN << BigInt(64)) / reserveM;
+        const priceN = (reserveM << BigInt(64)) / reserveN;
+
+        const after = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(BigInt(after.price_m_cumulative.toString()) - BigInt(before.price_m_cumulative.toString()), "Price of M should accumulate over the window")
+            .to.eql(priceM * elapsed);
+        expect(BigInt(after.price_n_cumulative.toString()) - BigInt(before.price_n_cumulative.toString()), "Price of N should accumulate over the window")
+            .to.eql(priceN * elapsed);
+        expect(after.last_update_ts.toString(), "Config 'last_update_ts' should move to the swap time")
+            .to.equal(clock.unixTimestamp.toString());
+    })
+
+    it("Record observations and read TWAPs back through observe", () => {
+        // Once created the oracle is required by every trade, so only the pool authority may create it
+        const initializeOracle = (signer: Keypair, cardinality: number) => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: payer.publicKey, isWritable: true, isSigner: true },
+                    { pubkey: signer.publicKey, isWritable: false, isSigner: true },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    { pubkey: oraclePda, isWritable: true, isSigner: false },
+                    { pubkey: SystemProgram.programId, isWritable: false, isSigner: false }
+                ],
+                programId,
+                data: coder.instruction.encode("initialize_oracle", { cardinality })
+            }));
+            tx.feePayer = payer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(payer, signer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        expect(initializeOracle(initializer, 4), "Only the pool authority should create the oracle")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(initializeOracle(authority, 1000), "Cardinality above the maximum should fail")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(initializeOracle(authority, 4) instanceof FailedTransactionMetadata, "The pool authority should create the oracle")
+            .to.be.false;
+
+        const clock = svm.getClock();
+        clock.unixTimestamp = clock.unixTimestamp + BigInt(300);
+        svm.setClock(clock);
+
+        const swapIx = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: oraclePda, isWritable: true, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: true,
+                amount_in: new anchor.BN(1_000_000),
+                min_out: new anchor.BN(0),
+                expiration: new anchor.BN((clock.unixTimestamp + BigInt(60)).toString()),
+            })
+        })
+
+        // Leaving the initialized oracle out would skip its observation, so the swap is rejected
+        const skipIx = new TransactionInstruction({
+            keys: swapIx.keys.map((key) => key.pubkey.equals(oraclePda)
+                ? { pubkey: programId, isWritable: false, isSigner: false }
+                : key),
+            programId,
+            data: swapIx.data
+        })
+        const skipTx = new Transaction().add(skipIx);
+        skipTx.feePayer = initializer.publicKey;
+        skipTx.recentBlockhash = svm.latestBlockhash();
+        skipTx.sign(initializer);
+        const skipped = svm.sendTransaction(skipTx);
+        expect(skipped instanceof FailedTransactionMetadata, "Swap without the initialized oracle should fail").to.be.true;
+        svm.expireBlockhash();
+
+        const swapTx = new Transaction().add(swapIx);
+        swapTx.feePayer = initializer.publicKey;
+        swapTx.recentBlockhash = svm.latestBlockhash();
+        swapTx.sign(initializer);
+        svm.sendTransaction(swapTx);
+
+        const oracle = coder.accounts.decode("Oracle", Buffer.from(svm.getAccount(oraclePda).data));
+        expect(oracle.index, "Swap should record a second observation").to.equal(1);
+        expect(oracle.cardinality, "Ring should grow into the allocated slots once it wraps").to.equal(4);
+
+        const config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        const reserveM = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount - BigInt(config.protocol_fees_m.toString());
+        const reserveN = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount - BigInt(config.protocol_fees_n.toString());
+
+        clock.unixTimestamp = clock.unixTimestamp + BigInt(300);
+        svm.setClock(clock);
+
+        const observeIx = new TransactionInstruction({
+            keys: [
+                { pubkey: configPda, isWritable: false, isSigner: false },
+                { pubkey: oraclePda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: false, isSigner: false },
+                { pubkey: vaultNAta, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false }
+            ],
+            programId,
+            data: coder.instruction.encode("observe", { seconds_agos: [300] })
+        })
+
+        const observeTx = new Transaction().add(observeIx);
+        observeTx.feePayer = payer.publicKey;
+        observeTx.recentBlockhash = svm.latestBlockhash();
+        observeTx.sign(payer);
+        const result = svm.simulateTransaction(observeTx);
+        expect(result instanceof FailedTransactionMetadata, "Observe should succeed").to.equal(false);
+
+        // Return data: Vec<Twap> = u32 length, then (u32 seconds_ago, u128 price_m, u128 price_n)
+        const data = Buffer.from((result as any).meta().returnData().data());
+        const priceM = data.readBigUInt64LE(8) + (data.readBigUInt64LE(16) << BigInt(64));
+        expect(priceM, "TWAP over the last 300s should equal the spot price held since the swap")
+            .to.eql((reserveN << BigInt(64)) / reserveM);
+    })
+
+    it("Swap a pegged pair on a StableSwap pool and ramp its amplification", () => {
+        const stableSeed = new anchor.BN(3);
+        const stableFee = new anchor.BN(5); // Pool 1 already holds the 30 bps tier for this pair
+        const stableAmount = new anchor.BN(3000 * 10 ** 6);
+
+        const [stableConfig] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            stableSeed.toArrayLike(Buffer, "le", 8),
+            usdcMint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const [stableLpMint] = PublicKey.findProgramAddressSync([
+            Buffer.from("liquiditypool"),
+            stableConfig.toBuffer()
+        ], programId);
+        const stableVaultM = getAssociatedTokenAddressSync(usdcMint, stableConfig, true);
+        const stableVaultN = getAssociatedTokenAddressSync(bonkMint, stableConfig, true);
+        const stableInitializerLp = getAssociatedTokenAddressSync(stableLpMint, initializer.publicKey, true);
+
+        const initIx = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: stableLpMint, isWritable: true, isSigner: false },
+                { pubkey: stableVaultM, isWritable: true, isSigner: false },
+                { pubkey: stableVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: stableInitializerLp, isWritable: true, isSigner: false },
+                { pubkey: stableConfig, isWritable: true, isSigner: false },
+                { pubkey: pairPda(usdcMint, bonkMint, stableFee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("initialize", {
+                seed: stableSeed,
+                authority: authority.publicKey,
+                fee: stableFee,
+                init_m_amount: stableAmount,
+                init_n_amount: stableAmount,
+                curve: { stableSwap: {} },
+                amp: new anchor.BN(100),
+                weight_m: 5000
+            })
+        })
+
+        const initTx = new Transaction().add(initIx);
+        initTx.feePayer = initializer.publicKey;
+        initTx.recentBlockhash = svm.latestBlockhash();
+        initTx.sign(initializer);
+        svm.sendTransaction(initTx);
+
+        const amountIn = BigInt(100 * 10 ** 6);
+        const bonkBefore = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount;
+        const clock = svm.getClock();
+
+        const swapIx = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: stableConfig, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: stableVaultM, isWritable: true, isSigner: false },
+                { pubkey: stableVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: true,
+                amount_in: new anchor.BN(amountIn.toString()),
+                min_out: new anchor.BN(0),
+                expiration: new anchor.BN((clock.unixTimestamp + BigInt(60)).toString()),
+            })
+        })
+
+        const swapTx = new Transaction().add(swapIx);
+        swapTx.feePayer = initializer.publicKey;
+        swapTx.recentBlockhash = svm.latestBlockhash();
+        swapTx.sign(initializer);
+        svm.sendTransaction(swapTx);
+
+        // x * y = k would pay out ~96.5 here; the flattened curve keeps the trade close to 1:1
+        const received = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount - bonkBefore;
+        const reserve = BigInt(stableAmount.toString());
+        const afterFee = amountIn * BigInt(9995) / BigInt(10000);
+        expect(received > reserve * afterFee / (reserve + afterFee), "StableSwap should beat constant product on a balanced pegged pool")
+            .to.be.true;
+        expect(received < afterFee, "StableSwap output should stay below the fee-adjusted input").to.be.true;
+
+        const rampEnd = clock.unixTimestamp + BigInt(7 * 86_400);
+        const rampIx = new TransactionInstruction({
+            keys: [
+                { pubkey: authority.publicKey, isWritable: false, isSigner: true },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: stableConfig, isWritable: true, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("ramp_amp", {
+                amp_target: new anchor.BN(500),
+                ramp_end_ts: new anchor.BN(rampEnd.toString()),
+            })
+        })
+
+        const rampTx = new Transaction().add(rampIx);
+        rampTx.feePayer = authority.publicKey;
+        rampTx.recentBlockhash = svm.latestBlockhash();
+        rampTx.sign(authority);
+        svm.sendTransaction(rampTx);
+
+        const config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(stableConfig).data));
+        expect(config.amp_initial.toString(), "Ramp should start from the current amplification").to.equal("100");
+        expect(config.amp_target.toString(), "Ramp should target the requested amplification").to.equal("500");
+        expect(config.amp_ramp_end_ts.toString(), "Ramp should end at the requested time").to.equal(rampEnd.toString());
+    })
+
+    it("Initialize an 80/20 weighted pool and swap against the weighted invariant", () => {
+        const weightedSeed = new anchor.BN(4);
+        const weightedFee = new anchor.BN(1);
+        const amountM = BigInt(8000 * 10 ** 6);
+        const amountN = BigInt(2000 * 10 ** 6);
+
+        const [weightedConfig] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            weightedSeed.toArrayLike(Buffer, "le", 8),
+            usdcMint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const [weightedLpMint] = PublicKey.findProgramAddressSync([
+            Buffer.from("liquiditypool"),
+            weightedConfig.toBuffer()
+        ], programId);
+        const weightedVaultM = getAssociatedTokenAddressSync(usdcMint, weightedConfig, true);
+        const weightedVaultN = getAssociatedTokenAddressSync(bonkMint, weightedConfig, true);
+        const weightedInitializerLp = getAssociatedTokenAddressSync(weightedLpMint, initializer.publicKey, true);
+
+        const initIx = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: weightedLpMint, isWritable: true, isSigner: false },
+                { pubkey: weightedVaultM, isWritable: true, isSigner: false },
+                { pubkey: weightedVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: weightedInitializerLp, isWritable: true, isSigner: false },
+                { pubkey: weightedConfig, isWritable: true, isSigner: false },
+                { pubkey: pairPda(usdcMint, bonkMint, weightedFee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("initialize", {
+                seed: weightedSeed,
+                authority: authority.publicKey,
+                fee: weightedFee,
+                init_m_amount: new anchor.BN(amountM.toString()),
+                init_n_amount: new anchor.BN(amountN.toString()),
+                curve: { weighted: {} },
+                amp: new anchor.BN(0),
+                weight_m: 8000
+            })
+        })
+
+        const initTx = new Transaction().add(initIx);
+        initTx.feePayer = initializer.publicKey;
+        initTx.recentBlockhash = svm.latestBlockhash();
+        initTx.sign(initializer);
+        svm.sendTransaction(initTx);
+
+        // Initial LP is the weighted invariant 8000^0.8 * 2000^0.2 (in base units), less the locked minimum
+        const lp = AccountLayout.decode(svm.getAccount(weightedInitializerLp).data).amount;
+        const invariant = Math.pow(Number(amountM), 0.8) * Math.pow(Number(amountN), 0.2);
+        expect(Math.abs(Number(lp) + 1000 - invariant) <= 1, "Initial LP should follow the weighted invariant").to.be.true;
+
+        const amountIn = BigInt(100 * 10 ** 6);
+        const bonkBefore = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount;
+        const clock = svm.getClock();
+
+        const swapIx = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: weightedConfig, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: weightedVaultM, isWritable: true, isSigner: false },
+                { pubkey: weightedVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: true,
+                amount_in: new anchor.BN(amountIn.toString()),
+                min_out: new anchor.BN(0),
+                expiration: new anchor.BN((clock.unixTimestamp + BigInt(60)).toString()),
+            })
+        })
+
+        const swapTx = new Transaction().add(swapIx);
+        swapTx.feePayer = initializer.publicKey;
+        swapTx.recentBlockhash = svm.latestBlockhash();
+        swapTx.sign(initializer);
+        svm.sendTransaction(swapTx);
+
+        // out = reserve_n * (1 - (reserve_m / (reserve_m + in))^(0.8 / 0.2))
+        const afterFee = Number(amountIn) * 0.9999;
+        const expected = Number(amountN) * (1 - Math.pow(Number(amountM) / (Number(amountM) + afterFee), 4));
+        const received = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount - bonkBefore;
+        expect(Math.abs(Number(received) - expected) <= 1, "Swap output should follow the weighted invariant").to.be.true;
+        expect(Number(received) <= expected, "Swap output should round in favour of the pool").to.be.true;
+    })
+
+    it("Provide concentrated liquidity in a range, swap through it and collect fees", () => {
+        const clSeed = new anchor.BN(5);
+        const clFee = new anchor.BN(100);
+        const tickSpacing = 10;
+        const [tickLower, tickUpper] = [-100, 100];
+        const liquidity = new anchor.BN(10 ** 12);
+        const q64 = new anchor.BN(1).shln(64);
+
+        const i32 = (value: number) => {
+            const buf = Buffer.alloc(4);
+            buf.writeInt32LE(value);
+            return buf;
+        };
+        const send = (ix: TransactionInstruction) => {
+            const tx = new Transaction().add(ix);
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            expect(res instanceof FailedTransactionMetadata, res.toString()).to.be.false;
+        };
+
+        const [clConfig] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            clSeed.toArrayLike(Buffer, "le", 8),
+            usdcMint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const clVaultM = getAssociatedTokenAddressSync(usdcMint, clConfig, true);
+        const clVaultN = getAssociatedTokenAddressSync(bonkMint, clConfig, true);
+
+        // Each array covers 60 ticks of spacing 10: [-600, 0) holds the lower tick, [0, 600) the upper
+        const [tickArrayLow] = PublicKey.findProgramAddressSync([Buffer.from("tick_array"), clConfig.toBuffer(), i32(-600)], programId);
+        const [tickArrayHigh] = PublicKey.findProgramAddressSync([Buffer.from("tick_array"), clConfig.toBuffer(), i32(0)], programId);
+        const [position] = PublicKey.findProgramAddressSync([
+            Buffer.from("range_position"),
+            clConfig.toBuffer(),
+            initializer.publicKey.toBuffer(),
+            i32(tickLower),
+            i32(tickUpper)
+        ], programId);
+
+        // Start at a price of 1.0
+        send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: clConfig, isWritable: true, isSigner: false },
+                { pubkey: pairPda(usdcMint, bonkMint, clFee), isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: clVaultM, isWritable: true, isSigner: false },
+                { pubkey: clVaultN, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("initialize_concentrated", {
+                seed: clSeed,
+                authority: authority.publicKey,
+                fee: clFee,
+                tick_spacing: tickSpacing,
+                sqrt_price_x64: q64,
+            })
+        }));
+
+        for (const [tickArray, start] of [[tickArrayLow, -600], [tickArrayHigh, 0]] as [PublicKey, number][]) {
+            send(new TransactionInstruction({
+                keys: [
+                    { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                    { pubkey: clConfig, isWritable: false, isSigner: false },
+                    { pubkey: tickArray, isWritable: true, isSigner: false },
+                    { pubkey: SystemProgram.programId, isWritable: false, isSigner: false }
+                ],
+                programId,
+                data: coder.instruction.encode("initialize_tick_array", { start_tick_index: start })
+            }));
+        }
+
+        send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: clConfig, isWritable: false, isSigner: false },
+                { pubkey: position, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false }
+            ],
+            programId,
+            data: coder.instruction.encode("open_range_position", { tick_lower: tickLower, tick_upper: tickUpper })
+        }));
+
+        const clock = svm.getClock();
+        const expiration = new anchor.BN((clock.unixTimestamp + BigInt(60)).toString());
+        const modifyKeys = [
+            { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+            { pubkey: usdcMint, isWritable: false, isSigner: false },
+            { pubkey: bonkMint, isWritable: false, isSigner: false },
+            { pubkey: clConfig, isWritable: true, isSigner: false },
+            { pubkey: protocolPda, isWritable: false, isSigner: false },
+            { pubkey: position, isWritable: true, isSigner: false },
+            { pubkey: tickArrayLow, isWritable: true, isSigner: false },
+            { pubkey: tickArrayHigh, isWritable: true, isSigner: false },
+            { pubkey: clVaultM, isWritable: true, isSigner: false },
+            { pubkey: clVaultN, isWritable: true, isSigner: false },
+            { pubkey: initializerMAta, isWritable: true, isSigner: false },
+            { pubkey: initializerNAta, isWritable: true, isSigner: false },
+            { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            ...eventCpiKeys
+        ];
+
+        send(new TransactionInstruction({
+            keys: modifyKeys,
+            programId,
+            data: coder.instruction.encode("increase_liquidity", {
+                liquidity,
+                max_m: new anchor.BN(10 ** 10),
+                max_n: new anchor.BN(10 ** 10),
+                expiration,
+            })
+        }));
+
+        // At the middle of a symmetric range both sides hold L * (1 - 1.0001^-50) of each token
+        const expectedSide = 10 ** 12 * (1 - Math.pow(1.0001, -50));
+        const vaultM = AccountLayout.decode(svm.getAccount(clVaultM).data).amount;
+        const vaultN = AccountLayout.decode(svm.getAccount(clVaultN).data).amount;
+        expect(Math.abs(Number(vaultM) - expectedSide) <= 2, "Vault M should hold the range's M side").to.be.true;
+        expect(Math.abs(Number(vaultN) - expectedSide) <= 2, "Vault N should hold the range's N side").to.be.true;
+
+        let config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(clConfig).data));
+        expect(config.liquidity.toString(), "In-range liquidity should be active").to.equal(liquidity.toString());
+
+        // Selling M moves the price down, starting in the array that holds tick 0
+        const amountIn = 10 * 10 ** 6;
+        const bonkBefore = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount;
+        send(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: clConfig, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: clVaultM, isWritable: true, isSigner: false },
+                { pubkey: clVaultN, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: tickArrayHigh, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: tickArrayLow, isWritable: true, isSigner: false },
+                { pubkey: programId, isWritable: false, isSigner: false }, // no third tick array
+                { pubkey: programId, isWritable: false, isSigner: false }, // no oracle
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap_concentrated", {
+                is_m: true,
+                amount_in: new anchor.BN(amountIn),
+                min_out: new anchor.BN(0),
+                expiration,
+            })
+        }));
+
+        // Within the range the pool behaves like x * y = L^2
+        const afterFee = amountIn * 0.99;
+        const expectedOut = afterFee * 10 ** 12 / (10 ** 12 + afterFee);
+        const received = AccountLayout.decode(svm.getAccount(initializerNAta).data).amount - bonkBefore;
+        expect(Math.abs(Number(received) - expectedOut) <= 2, "Swap output should follow the in-range curve").to.be.true;
+
+        config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(clConfig).data));
+        expect(config.tick_current, "Price should have moved below tick 0").to.equal(-1);
+
+        // The only position in range earns the whole swap fee, less rounding
+        const usdcBefore = AccountLayout.decode(svm.getAccount(initializerMAta).data).amount;
+        send(new TransactionInstruction({
+            keys: modifyKeys,
+            programId,
+            data: coder.instruction.encode("collect_fees", {})
+        }));
+        const collected = AccountLayout.decode(svm.getAccount(initializerMAta).data).amount - usdcBefore;
+        const swapFee = amountIn * 0.01;
+        expect(Number(collected) <= swapFee + 1 && Number(collected) >= swapFee - 2, "Position should collect the swap fee").to.be.true;
+
+        const rangePosition = coder.accounts.decode("RangePosition", Buffer.from(svm.getAccount(position).data));
+        expect(rangePosition.fees_owed_m.toNumber(), "Collected fees should be cleared").to.equal(0);
+
+        // A lock stops new liquidity but never traps what LPs have already provided
+        const setLocked = (locked: boolean) => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: authority.publicKey, isWritable: false, isSigner: true },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: clConfig, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode(locked ? "lock" : "unlock", {})
+            }));
+            tx.feePayer = authority.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(authority);
+            const res = svm.sendTransaction(tx);
+            expect(res instanceof FailedTransactionMetadata, res.toString()).to.be.false;
+        };
+        setLocked(true);
+        const halfLiquidity = liquidity.divn(2);
+        send(new TransactionInstruction({
+            keys: modifyKeys,
+            programId,
+            data: coder.instruction.encode("decrease_liquidity", {
+                liquidity: halfLiquidity,
+                min_m: new anchor.BN(0),
+                min_n: new anchor.BN(0),
+                expiration,
+            })
+        }));
+        setLocked(false);
+
+        const decreased = coder.accounts.decode("RangePosition", Buffer.from(svm.getAccount(position).data));
+        expect(decreased.liquidity.toString(), "Liquidity should come out of a locked pool").to.equal(liquidity.sub(halfLiquidity).toString());
+    })
+
+    it("Raise the fee with recent volatility in dynamic mode and decay it back to the base fee", () => {
+        // Dynamic fee settings are queued behind the fee timelock like any other fee change
+        const setDynamicFee = (enabled: boolean) => {
+            const effectiveAt = svm.getClock().unixTimestamp + BigInt(86_400);
+            const ix = new TransactionInstruction({
+                keys: [
+                    { pubkey: authority.publicKey, isWritable: false, isSigner: true },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode("set_dynamic_fee", {
+                    enabled,
+                    fee_min: 5,
+                    fee_max: 100,
+                    effective_at: new anchor.BN(effectiveAt.toString())
+                })
+            })
+
+            const tx = new Transaction().add(ix);
+            tx.feePayer = authority.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(authority);
+            svm.sendTransaction(tx);
+            svm.expireBlockhash();
+
+            const queued = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+            expect(queued.dynamic_fee, "Dynamic mode should not change before the timelock").to.equal(!enabled);
+            expect(queued.pending_dynamic_fee.enabled, "Config 'pending_dynamic_fee' should hold the queued mode").to.equal(enabled);
+
+            const clock = svm.getClock();
+            clock.unixTimestamp = effectiveAt;
+            svm.setClock(clock);
+            const applyTx = new Transaction().add(new TransactionInstruction({
+                keys: [{ pubkey: configPda, isWritable: true, isSigner: false }, ...eventCpiKeys],
+                programId,
+                data: coder.instruction.encode("apply_fee", {})
+            }));
+            applyTx.feePayer = payer.publicKey;
+            applyTx.recentBlockhash = svm.latestBlockhash();
+            applyTx.sign(payer);
+            svm.sendTransaction(applyTx);
+            svm.expireBlockhash();
+
+            const applied = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+            expect(applied.dynamic_fee, "Dynamic mode should switch once applied").to.equal(enabled);
+            expect(applied.pending_dynamic_fee, "Config 'pending_dynamic_fee' should be cleared after apply").to.be.null;
+        };
+        const effectiveFee = () => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [{ pubkey: configPda, isWritable: false, isSigner: false }],
+                programId,
+                data: coder.instruction.encode("effective_fee", {})
+            }));
+            tx.feePayer = payer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(payer);
+            const result = svm.simulateTransaction(tx);
+            expect(result instanceof FailedTransactionMetadata, "Effective fee should be readable").to.equal(false);
+            return Buffer.from((result as any).meta().returnData().data()).readUInt16LE(0);
+        };
+
+        setDynamicFee(true);
+        const baseFee = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data)).fee;
+        expect(effectiveFee(), "Without recent trading the base fee applies").to.equal(baseFee);
+
+        // Moving the price by well under 1% adds a surcharge that stays below the cap
+        const clock = svm.getClock();
+        const swapIx = new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: oraclePda, isWritable: true, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: true,
+                amount_in: new anchor.BN(10 * 10 ** 6),
+                min_out: new anchor.BN(0),
+                expiration: new anchor.BN((clock.unixTimestamp + BigInt(60)).toString()),
+            })
+        })
+
+        const swapTx = new Transaction().add(swapIx);
+        swapTx.feePayer = initializer.publicKey;
+        swapTx.recentBlockhash = svm.latestBlockhash();
+        swapTx.sign(initializer);
+        svm.sendTransaction(swapTx);
+
+        const config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(config.volatility.toNumber() > 0, "Swap should record its price move").to.be.true;
+        const raised = effectiveFee();
+        expect(raised > baseFee && raised < 100, "Recent volatility should raise the fee within the cap").to.be.true;
+
+        // An hour without trading halves the volatility twelve times
+        clock.unixTimestamp = clock.unixTimestamp + BigInt(3600);
+        svm.setClock(clock);
+        svm.expireBlockhash();
+        expect(effectiveFee(), "Fee should decay back to the base fee").to.equal(baseFee);
+
+        setDynamicFee(false);
+    })
+
+    it("Route a swap across two pools atomically under one min_out", () => {
+        const [weightedConfig] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            new anchor.BN(4).toArrayLike(Buffer, "le", 8),
+            usdcMint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const weightedVaultM = getAssociatedTokenAddressSync(usdcMint, weightedConfig, true);
+        const weightedVaultN = getAssociatedTokenAddressSync(bonkMint, weightedConfig, true);
+
+        // USDC -> BONK on the constant-product pool, then BONK -> USDC on the weighted pool
+        // Pool 1 has an observation buffer, so its hop must pass it; the program id stands in for none
+        const hop = (config: PublicKey, mintIn: PublicKey, mintOut: PublicKey, vaultIn: PublicKey, vaultOut: PublicKey, userIn: PublicKey, userOut: PublicKey, oracle: PublicKey) => [
+            { pubkey: config, isWritable: true, isSigner: false },
+            { pubkey: mintIn, isWritable: false, isSigner: false },
+            { pubkey: mintOut, isWritable: false, isSigner: false },
+            { pubkey: vaultIn, isWritable: true, isSigner: false },
+            { pubkey: vaultOut, isWritable: true, isSigner: false },
+            { pubkey: userIn, isWritable: true, isSigner: false },
+            { pubkey: userOut, isWritable: true, isSigner: false },
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            { pubkey: oracle, isWritable: !oracle.equals(programId), isSigner: false }
+        ];
+        const routeKeys = (pool1Oracle: PublicKey) => [
+            { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+            { pubkey: protocolPda, isWritable: false, isSigner: false },
+            ...eventCpiKeys,
+            ...hop(configPda, usdcMint, bonkMint, vaultMAta, vaultNAta, initializerMAta, initializerNAta, pool1Oracle),
+            ...hop(weightedConfig, bonkMint, usdcMint, weightedVaultN, weightedVaultM, initializerNAta, initializerMAta, programId)
+        ];
+
+        const amountIn = new anchor.BN(50 * 10 ** 6);
+        const clock = svm.getClock();
+        const route = (minOut: anchor.BN, keys = routeKeys(oraclePda)) => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys,
+                programId,
+                data: coder.instruction.encode("route_swap", {
+                    amount_in: amountIn,
+                    min_out: minOut,
+                    expiration: new anchor.BN((clock.unixTimestamp + BigInt(60)).toString()),
+                    hook_accounts: Buffer.from([0, 0]),
+                })
+            }));
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        const balances = () => [
+            AccountLayout.decode(svm.getAccount(initializerMAta).data).amount,
+            AccountLayout.decode(svm.getAccount(initializerNAta).data).amount
+        ];
+        const [usdcBefore, bonkBefore] = balances();
+
+        // An unreachable min_out on the final hop must undo the first hop as well
+        expect(route(new anchor.BN(10).pow(new anchor.BN(15)))).to.be.instanceOf(FailedTransactionMetadata);
+        expect(balances(), "A failed route should leave no partial execution behind").to.eql([usdcBefore, bonkBefore]);
+
+        expect(route(new anchor.BN(1), routeKeys(programId)), "A hop must not skip its pool's oracle")
+            .to.be.instanceOf(FailedTransactionMetadata);
+
+        const oracleIndex = () => coder.accounts.decode("Oracle", Buffer.from(svm.getAccount(oraclePda).data)).index;
+        const indexBefore = oracleIndex();
+        const nextClock = svm.getClock();
+        nextClock.unixTimestamp += BigInt(1);
+        svm.setClock(nextClock);
+        expect(route(new anchor.BN(1)) instanceof FailedTransactionMetadata, "Route should succeed").to.be.false;
+        expect(oracleIndex(), "The hop should record an observation").to.not.equal(indexBefore);
+        const [usdcAfter, bonkAfter] = balances();
+        expect(bonkAfter, "The intermediate BONK should be forwarded in full").to.eql(bonkBefore);
+        // The weighted pool prices BONK higher than the constant-product pool, so the round trip pays off
+        const returned = usdcAfter - (usdcBefore - BigInt(amountIn.toString()));
+        expect(returned > BigInt(amountIn.toString()), "Round trip should return more USDC than it spent").to.be.true;
+    })
+
+    it("Flash borrow from a vault and require repayment with the fee", () => {
+        const loan = BigInt(100 * 10 ** 6);
+        const config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        const fee = loan - loan * BigInt(10000 - config.fee) / BigInt(10000);
+
+        // The token program stands in for a receiver: its transfer repays the vault from the borrower
+        const flashLoan = (repay: bigint, oracle = oraclePda) => {
+            const repayData = Buffer.alloc(9);
+            repayData.writeUInt8(3, 0); // Transfer
+            repayData.writeBigUInt64LE(repay, 1);
+
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: initializer.publicKey, isWritable: false, isSigner: true },
+                    { pubkey: usdcMint, isWritable: false, isSigner: false },
+                    { pubkey: bonkMint, isWritable: false, isSigner: false },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                    { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: oracle, isWritable: !oracle.equals(programId), isSigner: false },
+                    ...eventCpiKeys,
+                    // Accounts forwarded to the receiver
+                    { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                    { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                    { pubkey: initializer.publicKey, isWritable: false, isSigner: true }
+                ],
+                programId,
+                data: coder.instruction.encode("flash_loan", {
+                    amount_m: new anchor.BN(loan.toString()),
+                    amount_n: new anchor.BN(0),
+                    data: repayData,
+                    hook_accounts: 0,
+                })
+            }));
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        const vaultBefore = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+
+        expect(flashLoan(loan), "Repaying without the fee should fail").to.be.instanceOf(FailedTransactionMetadata);
+        expect(flashLoan(loan + fee, programId), "A loan must not skip the pool's oracle").to.be.instanceOf(FailedTransactionMetadata);
+
+        const oracleIndex = () => coder.accounts.decode("Oracle", Buffer.from(svm.getAccount(oraclePda).data)).index;
+        const indexBefore = oracleIndex();
+        const clock = svm.getClock();
+        clock.unixTimestamp += BigInt(1);
+        svm.setClock(clock);
+        expect(flashLoan(loan + fee) instanceof FailedTransactionMetadata, "Repaying with the fee should succeed").to.be.false;
+        expect(oracleIndex(), "The loan should record an observation").to.not.equal(indexBefore);
+
+        const vaultAfter = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        expect(vaultAfter - vaultBefore, "The fee should stay in the vault").to.eql(fee);
+
+        // As on swaps, the treasury's share of the fee is set aside from the reserves
+        const accrued = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        expect(BigInt(accrued.protocol_fees_m.toString()) - BigInt(config.protocol_fees_m.toString()), "The treasury should accrue its share")
+            .to.eql(fee * BigInt(config.protocol_fee) / BigInt(10000));
+    })
+
+    it("Emit a Swap event through a self-CPI with reserves before and after", () => {
+        const amountIn = new anchor.BN(10 * 10 ** 6);
+        const vaultMBefore = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNBefore = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+
+        const tx = new Transaction().add(new TransactionInstruction({
+            keys: [
+                { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                { pubkey: usdcMint, isWritable: false, isSigner: false },
+                { pubkey: bonkMint, isWritable: false, isSigner: false },
+                { pubkey: configPda, isWritable: true, isSigner: false },
+                { pubkey: protocolPda, isWritable: false, isSigner: false },
+                { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                { pubkey: oraclePda, isWritable: true, isSigner: false },
+                ...eventCpiKeys
+            ],
+            programId,
+            data: coder.instruction.encode("swap", {
+                is_m: false,
+                amount_in: amountIn,
+                min_out: new anchor.BN(1),
+                expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+            })
+        }));
+        tx.feePayer = initializer.publicKey;
+        tx.recentBlockhash = svm.latestBlockhash();
+        tx.sign(initializer);
+        const res = svm.sendTransaction(tx);
+        svm.expireBlockhash();
+        expect(res instanceof FailedTransactionMetadata, "Swap should succeed").to.be.false;
+
+        const events = emittedEvents(res);
+        expect(events.length, "Exactly one event should be emitted").to.equal(1);
+
+        const event = events[0];
+        const config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        const vaultMAfter = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount;
+        const vaultNAfter = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        expect(event.name).to.equal("Swap");
+        expect(event.data.user.toBase58()).to.equal(initializer.publicKey.toBase58());
+        expect(event.data.is_m).to.be.false;
+        expect(event.data.amount_in.toString()).to.equal(amountIn.toString());
+        expect(BigInt(event.data.amount_out.toString()), "amount_out should match the vault's payout")
+            .to.eql(vaultMBefore - vaultMAfter);
+        expect(BigInt(event.data.reserve_n_after.toString()), "Reserves after should exclude protocol fees")
+            .to.eql(vaultNAfter - BigInt(config.protocol_fees_n.toString()));
+        expect(BigInt(event.data.reserve_n_after.toString()) - BigInt(event.data.reserve_n_before.toString()))
+            .to.eql(BigInt(amountIn.toString()) - BigInt(event.data.protocol_fee.toString()));
+        expect(vaultNAfter - vaultNBefore).to.eql(BigInt(amountIn.toString()));
+    })
+
+    it("Zap into the pool with BONK only", () => {
+        const amountIn = BigInt(200 * 10 ** 6);
+        const zapIn = (minLpOut: bigint) => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                    { pubkey: usdcMint, isWritable: false, isSigner: false },
+                    { pubkey: bonkMint, isWritable: false, isSigner: false },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: lpMint, isWritable: true, isSigner: false },
+                    { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                    { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                    { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: oraclePda, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode("zap_in", {
+                    is_m: false,
+                    amount_in: new anchor.BN(amountIn.toString()),
+                    min_lp_out: new anchor.BN(minLpOut.toString()),
+                    expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+                })
+            }));
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+        const balances = () => [initializerMAta, initializerNAta, initializerLpAta]
+            .map((ata) => AccountLayout.decode(svm.getAccount(ata).data).amount);
+
+        const [usdcBefore, bonkBefore, lpBefore] = balances();
+        const lpSupply = MintLayout.decode(svm.getAccount(lpMint).data).supply + BigInt(1000);
+        const reserveN = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount;
+        // Depositing half of it against USDC at the spot ratio is the upper bound; the swap leg costs its fee and price impact
+        const ceiling = amountIn * lpSupply / reserveN / BigInt(2);
+
+        expect(zapIn(ceiling * BigInt(2)), "An unreachable min_lp_out should fail").to.be.instanceOf(FailedTransactionMetadata);
+        expect(zapIn(ceiling * BigInt(98) / BigInt(100)) instanceof FailedTransactionMetadata, "Zap in should succeed").to.be.false;
+
+        const [usdcAfter, bonkAfter, lpAfter] = balances();
+        expect(bonkBefore - bonkAfter <= amountIn, "Zap should spend at most amount_in").to.be.true;
+        expect(bonkBefore - bonkAfter >= amountIn - BigInt(1000), "Zap should leave only dust unspent").to.be.true;
+        expect(usdcAfter >= usdcBefore, "Swapped USDC should be deposited, not taken from the user").to.be.true;
+        expect(lpAfter - lpBefore >= ceiling * BigInt(98) / BigInt(100), "LP minted should be close to the ceiling").to.be.true;
+    })
+
+    it("Zap out of the pool into USDC only", () => {
+        const zapOut = (lp: bigint, minOut: bigint) => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                    { pubkey: usdcMint, isWritable: false, isSigner: false },
+                    { pubkey: bonkMint, isWritable: false, isSigner: false },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: lpMint, isWritable: true, isSigner: false },
+                    { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                    { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerLpAta, isWritable: true, isSigner: false },
+                    { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: oraclePda, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode("zap_out", {
+                    is_m: true,
+                    amount: new anchor.BN(lp.toString()),
+                    min_out: new anchor.BN(minOut.toString()),
+                    expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+                })
+            }));
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+        const balances = () => [initializerMAta, initializerNAta, initializerLpAta]
+            .map((ata) => AccountLayout.decode(svm.getAccount(ata).data).amount);
+
+        // Withdraw pro rata, then sell the BONK share back against the reserves left behind
+        const lp = BigInt(50 * 10 ** 6);
+        const config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(configPda).data));
+        const totalLp = MintLayout.decode(svm.getAccount(lpMint).data).supply + BigInt(1000);
+        const reserveM = AccountLayout.decode(svm.getAccount(vaultMAta).data).amount - BigInt(config.protocol_fees_m.toString());
+        const reserveN = AccountLayout.decode(svm.getAccount(vaultNAta).data).amount - BigInt(config.protocol_fees_n.toString());
+        const m = reserveM * lp / totalLp;
+        const n = reserveN * lp / totalLp;
+        const nAfterFee = n * BigInt(10000 - config.fee) / BigInt(10000);
+        const expectedOut = m + (reserveM - m) * nAfterFee / (reserveN - n + nAfterFee);
+
+        const [usdcBefore, bonkBefore, lpBefore] = balances();
+        expect(zapOut(lp, expectedOut + BigInt(1)), "min_out above the quote should fail").to.be.instanceOf(FailedTransactionMetadata);
+        expect(zapOut(lp, expectedOut) instanceof FailedTransactionMetadata, "Zap out should succeed").to.be.false;
+
+        const [usdcAfter, bonkAfter, lpAfter] = balances();
+        expect(usdcAfter - usdcBefore, "Everything should be paid out in USDC").to.eql(expectedOut);
+        expect(bonkAfter, "No BONK should be paid out").to.eql(bonkBefore);
+        expect(lpBefore - lpAfter, "The LP should be burned").to.eql(lp);
+    })
+
+    it("Allow one pool per ordered mint pair and fee tier", () => {
+        const pair = coder.accounts.decode("PairRegistry", Buffer.from(svm.getAccount(pairPda(usdcMint, bonkMint, poolFee)).data));
+        expect(pair.config.toBase58(), "The registry should point at pool 1").to.equal(configPda.toBase58());
+
+        expect(initializePool(new anchor.BN(6), usdcMint, bonkMint, poolFee), "A second pool in the same tier should fail")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(initializePool(new anchor.BN(6), bonkMint, usdcMint, new anchor.BN(1)), "Reversed mints should fail")
+            .to.be.instanceOf(FailedTransactionMetadata);
+    })
+
+    it("Restrict pools to the protocol's fee tiers and pause the protocol", () => {
+        const updateProtocol = (name: string, args: object, signer: Keypair) => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: signer.publicKey, isWritable: false, isSigner: true },
+                    { pubkey: protocolPda, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode(name, args)
+            }));
+            tx.feePayer = payer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(payer, signer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+        const swap = () => {
+            const tx = new Transaction().add(new TransactionInstruction({
+                keys: [
+                    { pubkey: initializer.publicKey, isWritable: true, isSigner: true },
+                    { pubkey: usdcMint, isWritable: false, isSigner: false },
+                    { pubkey: bonkMint, isWritable: false, isSigner: false },
+                    { pubkey: configPda, isWritable: true, isSigner: false },
+                    { pubkey: protocolPda, isWritable: false, isSigner: false },
+                    { pubkey: vaultMAta, isWritable: true, isSigner: false },
+                    { pubkey: vaultNAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerMAta, isWritable: true, isSigner: false },
+                    { pubkey: initializerNAta, isWritable: true, isSigner: false },
+                    { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+                    { pubkey: oraclePda, isWritable: true, isSigner: false },
+                    ...eventCpiKeys
+                ],
+                programId,
+                data: coder.instruction.encode("swap", {
+                    is_m: true,
+                    amount_in: new anchor.BN(10 ** 6),
+                    min_out: new anchor.BN(1),
+                    expiration: new anchor.BN((svm.getClock().unixTimestamp + BigInt(60)).toString()),
+                })
+            }));
+            tx.feePayer = initializer.publicKey;
+            tx.recentBlockhash = svm.latestBlockhash();
+            tx.sign(initializer);
+            const res = svm.sendTransaction(tx);
+            svm.expireBlockhash();
+            return res;
+        };
+
+        const tierFee = new anchor.BN(50);
+        expect(initializePool(new anchor.BN(7), usdcMint, bonkMint, tierFee), "A fee outside the tiers should fail")
+            .to.be.instanceOf(FailedTransactionMetadata);
+
+        expect(updateProtocol("set_fee_tiers", { fee_tiers: [1, 5, 30, 50, 100] }, authority), "Only the admin can change the tiers")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(updateProtocol("set_fee_tiers", { fee_tiers: [30, 5] }, protocolAdmin), "Tiers must be increasing")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(updateProtocol("set_fee_tiers", { fee_tiers: [1, 5, 30, 50, 100, 500] }, protocolAdmin) instanceof FailedTransactionMetadata).to.be.false;
+        expect(updateProtocol("set_default_protocol_fee", { default_protocol_fee: 2000 }, protocolAdmin) instanceof FailedTransactionMetadata).to.be.false;
+        expect(updateProtocol("set_protocol_treasury", { treasury: payer.publicKey }, authority), "Only the admin can move the treasury")
+            .to.be.instanceOf(FailedTransactionMetadata);
+        expect(updateProtocol("set_protocol_treasury", { treasury: payer.publicKey }, protocolAdmin) instanceof FailedTransactionMetadata).to.be.false;
+
+        expect(initializePool(new anchor.BN(7), usdcMint, bonkMint, tierFee) instanceof FailedTransactionMetadata, "A new tier should be usable")
+            .to.be.false;
+        const [tierConfig] = PublicKey.findProgramAddressSync([
+            Buffer.from("config"),
+            new anchor.BN(7).toArrayLike(Buffer, "le", 8),
+            usdcMint.toBuffer(),
+            bonkMint.toBuffer()
+        ], programId);
+        const config = coder.accounts.decode("Config", Buffer.from(svm.getAccount(tierConfig).data));
+        expect(config.protocol_fee, "New pools should start with the default protocol fee").to.equal(2000);
+        expect(config.treasury.toBase58(), "New pools should pay the protocol's current treasury").to.equal(payer.publicKey.toBase58());
+
+        // Pausing stops trading and pool creation until the admin unpauses. Every other tier
+        // already has a USDC/BONK pool, so 500 bps can only fail here because of the pause.
+        expect(updateProtocol("set_protocol_paused", { paused: true }, protocolAdmin) instanceof FailedTransactionMetadata).to.be.false;
+        expect(swap(), "Swaps should fail while paused").to.be.instanceOf(FailedTransactionMetadata);
+        expect(initializePool(new anchor.BN(8), usdcMint, bonkMint, new anchor.BN(500)), "Pools cannot be created while paused")
+            .to.be.instanceOf(FailedTransactionMetadata);
+
+        expect(updateProtocol("set_protocol_paused", { paused: false }, protocolAdmin) instanceof FailedTransactionMetadata).to.be.false;
+        expect(swap() instanceof FailedTransactionMetadata, "Swaps should resume once unpaused").to.be.false;
+
+        // Both steps of an admin handover are published
+        const newAdmin = Keypair.generate();
+        const proposed = updateProtocol("propose_protocol_admin", { new_admin: newAdmin.publicKey }, protocolAdmin);
+        expect(proposed instanceof FailedTransactionMetadata, "The admin should propose a successor").to.be.false;
+        const [proposedEvent] = emittedEvents(proposed);
+        expect(proposedEvent.name).to.equal("ProtocolUpdated");
+        expect(proposedEvent.data.pending_admin.toBase58(), "The event should name the proposed admin").to.equal(newAdmin.publicKey.toBase58());
+
+        const accepted = updateProtocol("accept_protocol_admin", {}, newAdmin);
+        expect(accepted instanceof FailedTransactionMetadata, "The proposed admin should accept").to.be.false;
+        const [acceptedEvent] = emittedEvents(accepted);
+        expect(acceptedEvent.data.admin.toBase58(), "The event should name the new admin").to.equal(newAdmin.publicKey.toBase58());
+        expect(acceptedEvent.data.pending_admin, "No handover should be pending").to.be.null;
+    })
+});